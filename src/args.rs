@@ -1,11 +1,45 @@
 extern crate argparse;
 use self::argparse::*;
 
+use train_data::DataFormat;
+use training::TbLabels;
+
 pub struct Options {
     pub log_file_path: String,
     pub train_pgn: Option<String>,
     pub train_output_path: String,
     pub policy: bool,
+    pub data_format: DataFormat,
+    pub convert: Option<String>,
+    pub train_threads: usize,
+    pub train_min_elo: i32,
+    pub train_policy_min_elo: i32,
+    pub train_samples: usize,
+    pub train_skip_plies: usize,
+    pub train_min_frequency: u64,
+    pub train_quiet_only: bool,
+    pub train_syzygy_path: Option<String>,
+    pub train_tb_labels: TbLabels,
+    pub train_eval_weight: f32,
+    pub train_time_controls: String,
+    pub train_terminations: String,
+    pub train_variants: String,
+    pub fit_data: Option<String>,
+    pub fit_key_path: String,
+    pub fit_model_path: Option<String>,
+    pub fit_epochs: usize,
+    pub fit_learning_rate: f32,
+    pub fit_l2: f32,
+    pub selfplay_games: usize,
+    pub selfplay_nodes: u32,
+    pub selfplay_random_plies: usize,
+    pub selfplay_temperature_plies: usize,
+    pub selfplay_temperature: f32,
+    pub selfplay_pgn_path: String,
+    pub noise_alpha: f32,
+    pub noise_epsilon: f32,
+    pub gumbel_moves: usize,
+    pub seed: u32,
     pub extra: Vec<String>,
 }
 
@@ -16,6 +50,37 @@ impl Default for Options {
             train_pgn: None,
             train_output_path: "train_data.libsvm".into(),
             policy: false,
+            data_format: DataFormat::Libsvm,
+            convert: None,
+            train_threads: 0,
+            train_min_elo: 1700,
+            train_policy_min_elo: 2200,
+            train_samples: 4,
+            train_skip_plies: 2,
+            train_min_frequency: 500,
+            train_quiet_only: false,
+            train_syzygy_path: None,
+            train_tb_labels: TbLabels::Replace,
+            train_eval_weight: 0.,
+            train_time_controls: String::new(),
+            train_terminations: String::new(),
+            train_variants: "Standard".into(),
+            fit_data: None,
+            fit_key_path: "policy_key.txt".into(),
+            fit_model_path: None,
+            fit_epochs: 10,
+            fit_learning_rate: 0.05,
+            fit_l2: 1e-6,
+            selfplay_games: 0,
+            selfplay_nodes: 800,
+            selfplay_random_plies: 4,
+            selfplay_temperature_plies: 30,
+            selfplay_temperature: 1.,
+            selfplay_pgn_path: "selfplay.pgn".into(),
+            noise_alpha: 0.3,
+            noise_epsilon: 0.,
+            gumbel_moves: 0,
+            seed: 1,
             extra: Vec::new(),
         }
     }
@@ -40,6 +105,152 @@ pub fn init() {
             StoreTrue,
             "output policy data instead of value data",
         );
+        ap.refer(&mut options.train_threads).add_option(
+            &["--train-threads"],
+            Store,
+            "worker threads for --train (default all cores)",
+        );
+        ap.refer(&mut options.train_min_elo).add_option(
+            &["--min-elo"],
+            Store,
+            "skip --train games with a player rated below this",
+        );
+        ap.refer(&mut options.train_policy_min_elo).add_option(
+            &["--policy-min-elo"],
+            Store,
+            "--min-elo for policy data",
+        );
+        ap.refer(&mut options.train_samples).add_option(
+            &["--samples"],
+            Store,
+            "value positions sampled per game on average",
+        );
+        ap.refer(&mut options.train_skip_plies).add_option(
+            &["--skip-plies"],
+            Store,
+            "opening plies never sampled for value data",
+        );
+        ap.refer(&mut options.train_min_frequency).add_option(
+            &["--min-frequency"],
+            Store,
+            "drop value features seen in fewer positions",
+        );
+        ap.refer(&mut options.train_quiet_only).add_option(
+            &["--quiet-only"],
+            StoreTrue,
+            "sample only quiet positions for value data",
+        );
+        ap.refer(&mut options.train_syzygy_path).add_option(
+            &["--syzygy"],
+            StoreOption,
+            "label --train endgame positions from these tablebases",
+        );
+        ap.refer(&mut options.train_tb_labels).add_option(
+            &["--tb-labels"],
+            Store,
+            "replace the game result with the tablebase result, or add it as an extra label",
+        );
+        ap.refer(&mut options.train_eval_weight).add_option(
+            &["--eval-weight"],
+            Store,
+            "blend [%eval] comments into value labels with this weight",
+        );
+        ap.refer(&mut options.train_time_controls).add_option(
+            &["--time-controls"],
+            Store,
+            "comma separated TimeControl headers to keep (default all)",
+        );
+        ap.refer(&mut options.train_terminations).add_option(
+            &["--terminations"],
+            Store,
+            "comma separated Termination headers to keep (default all)",
+        );
+        ap.refer(&mut options.train_variants).add_option(
+            &["--variants"],
+            Store,
+            "comma separated Variant headers to keep",
+        );
+        ap.refer(&mut options.data_format).add_option(
+            &["--format"],
+            Store,
+            "training data format, libsvm or binary",
+        );
+        ap.refer(&mut options.convert).add_option(
+            &["--convert"],
+            StoreOption,
+            "convert training data to the other format, writing it to --output",
+        );
+        ap.refer(&mut options.fit_data).add_option(
+            &["--fit"],
+            StoreOption,
+            "fit a model to training data written by --train",
+        );
+        ap.refer(&mut options.fit_key_path).add_option(
+            &["--key"],
+            Store,
+            "policy key file written by --selfplay and read by --fit --policy",
+        );
+        ap.refer(&mut options.fit_model_path).add_option(
+            &["--model"],
+            StoreOption,
+            "where --fit writes the model (default model or policy_model)",
+        );
+        ap.refer(&mut options.fit_epochs)
+            .add_option(&["--epochs"], Store, "passes over the data in --fit");
+        ap.refer(&mut options.fit_learning_rate).add_option(
+            &["--learning-rate"],
+            Store,
+            "AdaGrad learning rate in --fit",
+        );
+        ap.refer(&mut options.fit_l2)
+            .add_option(&["--l2"], Store, "L2 regularization in --fit");
+        ap.refer(&mut options.selfplay_games).add_option(
+            &["--selfplay"],
+            Store,
+            "number of self-play games to generate training data from",
+        );
+        ap.refer(&mut options.selfplay_nodes).add_option(
+            &["--nodes"],
+            Store,
+            "playouts per move in self-play",
+        );
+        ap.refer(&mut options.selfplay_random_plies).add_option(
+            &["--random-plies"],
+            Store,
+            "random opening plies before self-play starts searching",
+        );
+        ap.refer(&mut options.selfplay_temperature_plies).add_option(
+            &["--temperature-plies"],
+            Store,
+            "plies in which self-play samples moves by visit count",
+        );
+        ap.refer(&mut options.selfplay_temperature).add_option(
+            &["--temperature"],
+            Store,
+            "temperature of the visit count sampling (0 = most visited move)",
+        );
+        ap.refer(&mut options.selfplay_pgn_path).add_option(
+            &["--pgn"],
+            Store,
+            "where self-play writes its games",
+        );
+        ap.refer(&mut options.noise_alpha).add_option(
+            &["--noise-alpha"],
+            Store,
+            "Dirichlet alpha of the root noise in self-play (0.001 to 10)",
+        );
+        ap.refer(&mut options.noise_epsilon).add_option(
+            &["--noise-epsilon"],
+            Store,
+            "share of the root priors replaced by noise in self-play (0 = off)",
+        );
+        ap.refer(&mut options.gumbel_moves).add_option(
+            &["--gumbel-moves"],
+            Store,
+            "root moves sampled by a Gumbel root search in self-play (0 = off)",
+        );
+        ap.refer(&mut options.seed)
+            .add_option(&["--seed"], Store, "random seed");
         ap.refer(&mut options.log_file_path)
             .add_option(&["--log"], Store, "log file path");
         ap.refer(&mut options.extra).add_argument(
@@ -16,6 +16,12 @@ fn build_value() {
     let magic_path = Path::new(&out_dir).join("feature_const.rs");
     let mut f = File::create(&magic_path).unwrap();
     let names = read_all_to_string("feature_list.txt").unwrap();
+    writeln!(
+        f,
+        "pub const FEATURE_SET_HASH: u64 = {:#x};",
+        fnv_hash(names.as_bytes())
+    )
+    .unwrap();
     let mut nxt = 0;
     let phases = ["Midgame", "Endgame"];
     writeln!(f, "#[repr(u8)]").unwrap();
@@ -25,6 +31,16 @@ fn build_value() {
         writeln!(f, "    {} = {},", p, i).unwrap()
     }
     writeln!(f, "}}").unwrap();
+    // Material counted towards the midgame phase, by piece in `chess::ALL_PIECES` order.
+    let phase_piece_weights = [0, 1, 1, 2, 4, 0];
+    writeln!(
+        f,
+        "const PHASE_PIECE_WEIGHTS: [u32; {}] = {:?};",
+        phase_piece_weights.len(),
+        phase_piece_weights
+    )
+    .unwrap();
+    writeln!(f, "const MAX_PHASE: u32 = 24;").unwrap();
     let colours = 2;
     let names = expand_macros(
         names
@@ -69,6 +85,12 @@ fn build_policy() {
     let mut f = File::create(&magic_path).unwrap();
     let offset = "NUM_ENCODED";
     let names = write_feature_names("policy_feature_list.txt", &mut f, offset);
+    writeln!(
+        f,
+        "pub const POLICY_FEATURE_SET_HASH: u64 = {:#x};",
+        fnv_hash(names.join(" ").as_bytes())
+    )
+    .unwrap();
     let num_names = names.len();
     writeln!(
         f,
@@ -111,8 +133,18 @@ fn write_feature_names(from: &str, f: &mut File, offset: &str) -> Vec<String> {
     names
 }
 
+/// Identifies a feature list in training data headers.
+fn fnv_hash(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x100_0000_01b3);
+    }
+    h
+}
+
 fn exempt(name: &str) -> bool {
-    if name.contains("PAWN_TO_RANK") || name.contains("_TO_") {
+    if name.contains("PAWN_TO_RANK") || name.contains("_TO_") || name.contains("PAWN_RANK") {
         true
     } else if name.contains("NUM") {
         false
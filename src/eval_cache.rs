@@ -0,0 +1,165 @@
+use arc_swap::ArcSwapOption;
+use atomics::*;
+use mcts::thousands_separate;
+use once_cell::sync::Lazy;
+use options::get_eval_cache_bytes;
+use std;
+use std::sync::Arc;
+
+const MAX_CACHED_MOVES: usize = 64;
+const POLICY_WORDS: usize = MAX_CACHED_MOVES / 4;
+const DATA_WORDS: usize = 2 + POLICY_WORDS;
+
+/// A lockless entry: the stored key is the hash xor'ed with every data word,
+/// so a torn write shows up as a miss instead of a wrong evaluation.
+#[derive(Default)]
+struct Entry {
+    key: AtomicU64,
+    data: [AtomicU64; DATA_WORDS],
+}
+
+static SHARED: Lazy<ArcSwapOption<EvalCache>> = Lazy::new(ArcSwapOption::empty);
+
+/// The cache kept between searches. It is reallocated when its size changes
+/// and dropped by `clear_eval_cache`.
+pub fn shared_eval_cache() -> Arc<EvalCache> {
+    let size_bytes = get_eval_cache_bytes();
+    if let Some(cache) = SHARED.load_full() {
+        if cache.size_bytes == size_bytes {
+            return cache;
+        }
+    }
+    let cache = Arc::new(EvalCache::new(size_bytes));
+    SHARED.store(Some(cache.clone()));
+    cache
+}
+
+/// Forgets all cached evaluations, for a new game or a different evaluator.
+pub fn clear_eval_cache() {
+    SHARED.store(None);
+}
+
+/// Caches the value score and the move evaluations of a position by
+/// `State::eval_hash`. Positions with more than `MAX_CACHED_MOVES` moves are
+/// not cached.
+pub struct EvalCache {
+    size_bytes: usize,
+    arr: Box<[Entry]>,
+    mask: usize,
+    lookups: AtomicUsize,
+    hits: AtomicUsize,
+}
+
+fn quantize(x: f32) -> u64 {
+    (x.clamp(0., 1.) * u16::MAX as f32).round() as u64
+}
+
+fn dequantize(x: u64) -> f32 {
+    (x & 0xFFFF) as f32 / u16::MAX as f32
+}
+
+impl EvalCache {
+    pub fn new(size_bytes: usize) -> Self {
+        let capacity = match size_bytes / std::mem::size_of::<Entry>() {
+            0 => 0,
+            n => (n + 1).next_power_of_two() / 2,
+        };
+        let arr = (0..capacity).map(|_| Entry::default()).collect();
+        Self {
+            size_bytes,
+            arr,
+            mask: capacity.wrapping_sub(1),
+            lookups: AtomicUsize::default(),
+            hits: AtomicUsize::default(),
+        }
+    }
+
+    pub fn lookup(&self, hash: u64, num_moves: usize) -> Option<(Vec<f32>, i64)> {
+        if self.arr.is_empty() || num_moves > MAX_CACHED_MOVES {
+            return None;
+        }
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        let entry = &self.arr[hash as usize & self.mask];
+        let key = entry.key.load(Ordering::Relaxed) as u64;
+        let mut data = [0u64; DATA_WORDS];
+        let mut check = 0;
+        for (x, y) in data.iter_mut().zip(entry.data.iter()) {
+            *x = y.load(Ordering::Relaxed) as u64;
+            check ^= *x;
+        }
+        if key ^ check != hash || data[1] != num_moves as u64 {
+            return None;
+        }
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        let policy = (0..num_moves)
+            .map(|i| dequantize(data[2 + i / 4] >> (16 * (i % 4))))
+            .collect();
+        Some((policy, data[0] as i64))
+    }
+
+    /// Whether a position is cached, without counting a lookup.
+    pub fn contains(&self, hash: u64) -> bool {
+        if self.arr.is_empty() {
+            return false;
+        }
+        let entry = &self.arr[hash as usize & self.mask];
+        let check = entry
+            .data
+            .iter()
+            .fold(0, |check, x| check ^ x.load(Ordering::Relaxed) as u64);
+        entry.key.load(Ordering::Relaxed) as u64 ^ check == hash
+    }
+
+    pub fn insert(&self, hash: u64, policy: &[f32], value: i64) {
+        if self.arr.is_empty() || policy.len() > MAX_CACHED_MOVES {
+            return;
+        }
+        let mut data = [0u64; DATA_WORDS];
+        data[0] = value as u64;
+        data[1] = policy.len() as u64;
+        for (i, &p) in policy.iter().enumerate() {
+            data[2 + i / 4] |= quantize(p) << (16 * (i % 4));
+        }
+        let entry = &self.arr[hash as usize & self.mask];
+        let mut check = 0;
+        for (x, y) in data.iter().zip(entry.data.iter()) {
+            y.store(*x as FakeU64, Ordering::Relaxed);
+            check ^= *x;
+        }
+        entry
+            .key
+            .store((hash ^ check) as FakeU64, Ordering::Relaxed);
+    }
+
+    pub fn diagnose(&self) -> String {
+        let lookups = self.lookups.load(Ordering::Relaxed);
+        let hits = self.hits.load(Ordering::Relaxed);
+        format!(
+            "{} eval cache hits ({:.1}% of {} lookups)\n",
+            thousands_separate(hits),
+            100. * hits as f64 / std::cmp::max(1, lookups) as f64,
+            thousands_separate(lookups)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let cache = EvalCache::new(1 << 16);
+        let policy = [0.5, 0.25, 0.125, 0.0625, 0.0625];
+        cache.insert(0x1234_5678_9abc_def0, &policy, -42);
+        let (p, v) = cache.lookup(0x1234_5678_9abc_def0, policy.len()).unwrap();
+        assert_eq!(v, -42);
+        for (a, b) in p.iter().zip(policy.iter()) {
+            assert!((a - b).abs() < 1e-4);
+        }
+        assert!(cache.lookup(0x1234_5678_9abc_def0, 4).is_none());
+        assert!(cache.contains(0x1234_5678_9abc_def0));
+        assert!(!cache.contains(0x1234_5678_9abc_def1));
+        assert!(cache.lookup(0x1234_5678_9abc_def1, 5).is_none());
+    }
+}
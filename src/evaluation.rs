@@ -1,17 +1,100 @@
 use chess::*;
+use eval_cache::{shared_eval_cache, EvalCache};
 use features::Model;
-use mcts::{Evaluator, SearchHandle};
+use mate_search::{prove, MateProof};
+use mcts::{Evaluator, GameState, SearchHandle};
+use network::{loaded_network, Network};
+use options::{get_mate_search_plies, get_quiescence_nodes, get_use_network_eval};
 use policy_features::evaluate_moves;
 use search::{GooseMCTS, SCALE};
+use see::see;
 use state::{MoveList, Outcome, Player, State};
+use std::sync::Arc;
+
+fn terminal_evaluation(state: &State) -> i64 {
+    let x = SCALE as i64;
+    match state.outcome() {
+        Outcome::Draw => 0,
+        Outcome::WhiteWin => x,
+        Outcome::BlackWin => -x,
+        Outcome::Ongoing => unreachable!(),
+    }
+}
+
+fn interpret_for_player(evaln: i64, player: &Player) -> i64 {
+    match *player {
+        Color::White => evaln,
+        Color::Black => -evaln,
+    }
+}
+
+/// Capture-only alpha-beta for the side to move, standing pat on the linear
+/// model. It searches over `State` rather than `Board` because the model reads
+/// mobility and the previous capture. Captures that lose material by static
+/// exchange are skipped, and each searched capture uses up one node of `budget`.
+fn quiescence(model: &Model, state: &State, mut alpha: f32, beta: f32, budget: &mut usize) -> f32 {
+    let sign = match state.board().side_to_move() {
+        Color::White => 1.,
+        Color::Black => -1.,
+    };
+    if state.outcome() != &Outcome::Ongoing {
+        return sign * terminal_evaluation(state) as f32 / SCALE;
+    }
+    let stand_pat = sign * model.score(state);
+    if stand_pat >= beta || *budget == 0 {
+        return stand_pat;
+    }
+    alpha = alpha.max(stand_pat);
+
+    let board = state.board();
+    let mut captures = MoveGen::new_legal(board);
+    captures.set_iterator_mask(*board.color_combined(!board.side_to_move()));
+    let mut captures: Vec<(i32, ChessMove)> = captures
+        .map(|m| (see(board, m), m))
+        .filter(|&(gain, _)| gain >= 0)
+        .collect();
+    captures.sort_by_key(|&(gain, _)| -gain);
+
+    for (_, m) in captures {
+        if *budget == 0 {
+            break;
+        }
+        *budget -= 1;
+        let mut next = state.clone();
+        next.make_move(&m);
+        let score = -quiescence(model, &next, -beta, -alpha, budget);
+        if score >= beta {
+            return score;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
 
 pub struct GooseEval {
     model: Model,
+    quiescence_nodes: usize,
 }
 
 impl GooseEval {
-    pub fn new(model: Model) -> Self {
-        Self { model }
+    /// `quiescence_nodes` bounds the capture search at each new node; 0 turns it off.
+    pub fn new(model: Model, quiescence_nodes: usize) -> Self {
+        Self {
+            model,
+            quiescence_nodes,
+        }
+    }
+
+    fn score(&self, state: &State) -> f32 {
+        if self.quiescence_nodes == 0 {
+            return self.model.score(state);
+        }
+        let mut budget = self.quiescence_nodes;
+        let score = quiescence(&self.model, state, -2., 2., &mut budget);
+        match state.board().side_to_move() {
+            Color::White => score,
+            Color::Black => -score,
+        }
     }
 }
 
@@ -21,15 +104,49 @@ impl Evaluator<GooseMCTS> for GooseEval {
     fn evaluate_new_state(&self, state: &State, moves: &MoveList) -> (Vec<f32>, i64) {
         let move_evaluations = evaluate_moves(state, moves.as_slice());
         let state_evaluation = if moves.len() == 0 {
-            let x = SCALE as i64;
-            match state.outcome() {
-                Outcome::Draw => 0,
-                Outcome::WhiteWin => x,
-                Outcome::BlackWin => -x,
-                Outcome::Ongoing => unreachable!(),
-            }
+            terminal_evaluation(state)
+        } else {
+            (self.score(state) * SCALE as f32) as i64
+        };
+        (move_evaluations, state_evaluation)
+    }
+    fn evaluate_existing_state(&self, _: &State, evaln: &i64, _: SearchHandle<GooseMCTS>) -> i64 {
+        *evaln
+    }
+    fn interpret_evaluation_for_player(&self, evaln: &i64, player: &Player) -> i64 {
+        interpret_for_player(*evaln, player)
+    }
+}
+
+/// Scores positions with a `Network` instead of the linear model.
+/// Move evaluations still come from the policy model.
+pub struct NetworkEval {
+    network: Arc<Network>,
+}
+
+impl NetworkEval {
+    pub fn new(network: Arc<Network>) -> Self {
+        Self { network }
+    }
+}
+
+impl Evaluator<GooseMCTS> for NetworkEval {
+    type StateEvaluation = i64;
+
+    fn evaluate_new_state(&self, state: &State, moves: &MoveList) -> (Vec<f32>, i64) {
+        let move_evaluations = evaluate_moves(state, moves.as_slice());
+        let state_evaluation = if moves.len() == 0 {
+            terminal_evaluation(state)
         } else {
-            (self.model.score(state) * SCALE as f32) as i64
+            let score = match state.network_accumulator() {
+                Some(acc) if Arc::ptr_eq(acc.network(), &self.network) => acc.score(state.board()),
+                _ => self.network.score(state.board()),
+            };
+            let score = match state.board().side_to_move() {
+                Color::White => score,
+                Color::Black => -score,
+            };
+            (score * SCALE) as i64
         };
         (move_evaluations, state_evaluation)
     }
@@ -37,13 +154,82 @@ impl Evaluator<GooseMCTS> for GooseEval {
         *evaln
     }
     fn interpret_evaluation_for_player(&self, evaln: &i64, player: &Player) -> i64 {
-        match *player {
-            Color::White => *evaln,
-            Color::Black => -*evaln,
+        interpret_for_player(*evaln, player)
+    }
+}
+
+enum Backend {
+    Linear(GooseEval),
+    Network(NetworkEval),
+}
+
+/// The evaluator used by the search. The backend is selected with the
+/// `Evaluator` UCI option, and its results are kept in an `EvalCache`.
+pub struct SearchEval {
+    backend: Backend,
+    cache: Arc<EvalCache>,
+    mate_search_plies: usize,
+}
+
+impl SearchEval {
+    pub fn new() -> Self {
+        let mut backend = Backend::Linear(GooseEval::new(Model::new(), get_quiescence_nodes()));
+        if get_use_network_eval() {
+            match loaded_network() {
+                Some(network) => backend = Backend::Network(NetworkEval::new(network)),
+                None => warn!("No network loaded (set EvalFile), using the linear evaluator"),
+            }
+        }
+        Self {
+            backend,
+            cache: shared_eval_cache(),
+            mate_search_plies: get_mate_search_plies(),
         }
     }
 }
 
+impl Evaluator<GooseMCTS> for SearchEval {
+    type StateEvaluation = i64;
+
+    fn evaluate_new_state(&self, state: &State, moves: &MoveList) -> (Vec<f32>, i64) {
+        let hash = state.eval_hash();
+        if moves.len() != 0 {
+            if let Some(result) = self.cache.lookup(hash, moves.len()) {
+                return result;
+            }
+        }
+        let (move_evaluations, state_evaluation) = match self.backend {
+            Backend::Linear(ref e) => e.evaluate_new_state(state, moves),
+            Backend::Network(ref e) => e.evaluate_new_state(state, moves),
+        };
+        if moves.len() != 0 {
+            self.cache.insert(hash, &move_evaluations, state_evaluation);
+        }
+        (move_evaluations, state_evaluation)
+    }
+    fn evaluate_existing_state(&self, _: &State, evaln: &i64, _: SearchHandle<GooseMCTS>) -> i64 {
+        *evaln
+    }
+    fn interpret_evaluation_for_player(&self, evaln: &i64, player: &Player) -> i64 {
+        interpret_for_player(*evaln, player)
+    }
+    fn proven_evaluation(&self, state: &State) -> Option<i64> {
+        // proven positions are never cached, so a cached one was not provable
+        if self.mate_search_plies == 0 || self.cache.contains(state.eval_hash()) {
+            return None;
+        }
+        let x = SCALE as i64;
+        let evaln = match prove(state.board(), self.mate_search_plies)? {
+            MateProof::Win => x,
+            MateProof::Loss => -x,
+        };
+        Some(interpret_for_player(evaln, &state.board().side_to_move()))
+    }
+    fn diagnose(&self) -> String {
+        self.cache.diagnose()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,6 +273,19 @@ mod tests {
         manager.principal_variation_states(pv_len)
     }
 
+    #[test]
+    fn quiescence_sees_hanging_queen() {
+        let model = Model::new();
+        let state = State::from_fen("4k3/8/8/3q4/8/8/7P/3QK3 w - - 0 1").unwrap();
+        let mut budget = 0;
+        let stand_pat = quiescence(&model, &state, -2., 2., &mut budget);
+        assert_eq!(stand_pat, model.score(&state));
+        let mut budget = 16;
+        let searched = quiescence(&model, &state, -2., 2., &mut budget);
+        assert!(budget < 16);
+        assert!(searched > stand_pat + 0.2, "{} {}", searched, stand_pat);
+    }
+
     #[test]
     fn mate_in_one() {
         assert_find_move("6k1/8/6K1/8/8/8/8/R7 w - - 0 0", "a1a8");
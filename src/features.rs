@@ -2,8 +2,10 @@ use chess::*;
 use state::State;
 use std::cmp::min;
 use std::io::Write;
+use train_data::{write_row, DataFormat};
 
 use features_common::*;
+use pawn_structure::{pawn_structure, PawnStructure};
 
 include!(concat!(env!("OUT_DIR"), "/feature_const.rs"));
 const MAX_PATTERNS_IN_POSITION: usize = 64 + 7 * 7;
@@ -55,8 +57,8 @@ fn index_2x2_pattern(pattern: &[usize; 4]) -> usize {
 }
 
 pub struct FeatureVec {
-    pub arr: Vec<u8>,
-    pub patterns: Vec<usize>,
+    pub arr: Vec<f32>,
+    pub patterns: Vec<(usize, f32)>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -65,7 +67,7 @@ pub enum GameResult {
     BlackWin,
     Draw,
 }
-const NUM_OUTCOMES: usize = 3;
+pub const NUM_OUTCOMES: usize = 3;
 impl GameResult {
     pub fn flip(&self) -> Self {
         match *self {
@@ -77,37 +79,41 @@ impl GameResult {
 }
 
 impl FeatureVec {
-    pub fn write_libsvm<W: Write, Pred: Fn(usize) -> bool>(
+    /// Writes the features as one row of training data, summing repeated patterns.
+    pub fn write_row<W: Write, Pred: Fn(usize) -> bool>(
         &mut self,
         f: &mut W,
-        label: usize,
+        format: DataFormat,
+        label: &[f32],
         whitelist: Pred,
     ) {
-        write!(f, "{}", label).unwrap();
+        let mut entries = Vec::new();
         for (index, value) in self.arr.iter().enumerate() {
-            if *value != 0 && whitelist(index) {
-                write!(f, " {}:{}", index + 1, value).unwrap();
+            if *value != 0. && whitelist(index) {
+                entries.push((index, *value));
             }
         }
-        self.patterns.sort_unstable();
-        let mut cnt = 0;
+        self.patterns.sort_unstable_by_key(|x| x.0);
+        let mut sum = 0.;
         for i in 0..self.patterns.len() {
-            let x = self.patterns[i];
-            cnt += 1;
-            if i + 1 == self.patterns.len() || x != self.patterns[i + 1] {
+            let (x, v) = self.patterns[i];
+            sum += v;
+            if i + 1 == self.patterns.len() || x != self.patterns[i + 1].0 {
                 if whitelist(x) {
-                    write!(f, " {}:{}", x + 1, cnt).unwrap();
+                    entries.push((x, sum));
                 }
-                cnt = 0;
+                sum = 0.;
             }
         }
-        write!(f, "\n").unwrap();
+        write_row(f, format, label, &entries).unwrap();
     }
-    pub fn write_frequency(&self, freq: &mut [u64; NUM_FEATURES]) {
+    pub fn write_frequency(&self, freq: &mut [u64]) {
         for (index, &value) in self.arr.iter().enumerate() {
-            freq[index] += value as u64;
+            if value != 0. {
+                freq[index] += 1;
+            }
         }
-        for &x in &self.patterns {
+        for &(x, _) in &self.patterns {
             freq[x] += 1;
         }
     }
@@ -130,9 +136,9 @@ fn encode_move(c: Color, p: Piece, to: Square) -> usize {
     x + CAN_DO_PAWN_TO_A1
 }
 
-fn foreach_feature<F>(state: &State, mut f: F)
+fn foreach_dense_feature<F>(state: &State, mut f: F)
 where
-    F: FnMut(usize, u8),
+    F: FnMut(usize, f32),
 {
     let board = state.board();
     let colors = &[board.side_to_move(), !board.side_to_move()];
@@ -151,9 +157,17 @@ where
         Piece::Queen,
         Piece::King,
     ];
-    let p = phase(state);
+    let weights = phase_weights(board);
+    let pawns = pawn_structure(board);
+    let mut emit = |c: usize, idx: usize| {
+        for &p in &PHASES {
+            if weights[p as usize] != 0. {
+                f(feature_index(c, p, idx), weights[p as usize]);
+            }
+        }
+    };
     for (color_index, &c) in colors.iter().enumerate() {
-        let moves = &state.move_lists()[color_index];
+        let moves = state.move_list(color_index);
         let color_board = board.color_combined(c);
         for &piece in non_king_pieces {
             let cnt = (board.pieces(piece) & color_board).popcnt();
@@ -180,7 +194,7 @@ where
                 (Piece::Queen, _) => QUEEN_NUM_1,
                 (Piece::King, _) => unreachable!(),
             };
-            f(feature_index(color_index, p, feat), 1);
+            emit(color_index, feat);
         }
         let prev_dest = state
             .prev_move()
@@ -190,107 +204,310 @@ where
         for mov in moves {
             if let Some(a) = board.piece_on(mov.get_source()) {
                 let b = board.piece_on(mov.get_dest()).unwrap_or(Piece::King);
-                f(
-                    feature_index(color_index, p, CAN_DO_PAWN_TAKES_PAWN + encode_pair(a, b)),
-                    1,
-                );
+                emit(color_index, CAN_DO_PAWN_TAKES_PAWN + encode_pair(a, b));
                 if mov.get_dest() == prev_dest {
                     best_recapture = min(best_recapture, a);
                 }
-                f(
-                    feature_index(color_index, p, encode_move(c, a, mov.get_dest())),
-                    1,
-                );
+                emit(color_index, encode_move(c, a, mov.get_dest()));
             }
         }
         if let Some(captured) = state.prev_capture() {
-            f(
-                feature_index(
-                    color_index,
-                    p,
-                    JUST_CAPTURED_PAWN_RECAPTURE_WITH_PAWN + encode_pair(captured, best_recapture),
-                ),
-                1,
+            emit(
+                color_index,
+                JUST_CAPTURED_PAWN_RECAPTURE_WITH_PAWN + encode_pair(captured, best_recapture),
             );
         }
         if color_index == 0 {
             if board.checkers().0 != 0 {
-                f(feature_index(color_index, p, IS_CHECK), 1);
+                emit(color_index, IS_CHECK);
                 if board.checkers().popcnt() != 1 {
-                    f(feature_index(color_index, p, IS_DOUBLE_CHECK), 1);
+                    emit(color_index, IS_DOUBLE_CHECK);
                 }
             }
             let pinned = board.pinned();
             for &piece in all_pieces {
                 if (board.pieces(piece) & pinned).0 != 0 {
-                    f(
-                        feature_index(color_index, p, PAWN_PINNED + piece as usize),
-                        1,
-                    );
+                    emit(color_index, PAWN_PINNED + piece as usize);
                 }
             }
         }
-        f(feature_index(color_index, p, ONE), 1);
-    }
-    for &color in colors {
-        let color_board = board.color_combined(color);
-        for &piece in all_pieces {
-            for sq in board.pieces(piece) & color_board {
-                for &is_rank in &[false, true] {
-                    if piece != Piece::Pawn
-                        && piece != Piece::Rook
-                        && piece != Piece::King
-                        && is_rank
-                        && sq.get_rank() != Rank::First
-                        && sq.get_rank() != Rank::Eighth
-                    {
-                        continue;
-                    }
-                    if piece != Piece::Pawn
-                        && piece != Piece::King
-                        && piece != Piece::Knight
-                        && !is_rank
-                    {
-                        continue;
-                    }
-                    f(
-                        pattern_index(p, FullPiece { color, role: piece }, sq, is_rank),
-                        1,
-                    );
-                }
-            }
+        foreach_king_safety_feature(board, c, |idx| emit(color_index, idx));
+        foreach_pawn_structure_feature(board, &pawns, c, |idx| emit(color_index, idx));
+        emit(color_index, ONE);
+    }
+}
+
+fn piece_attacks(piece: Piece, sq: Square, color: Color, occupied: BitBoard) -> BitBoard {
+    match piece {
+        Piece::Pawn => get_pawn_attacks(sq, color, !EMPTY),
+        Piece::Knight => get_knight_moves(sq),
+        Piece::Bishop => get_bishop_moves(sq, occupied),
+        Piece::Rook => get_rook_moves(sq, occupied),
+        Piece::Queen => get_bishop_moves(sq, occupied) | get_rook_moves(sq, occupied),
+        Piece::King => get_king_moves(sq),
+    }
+}
+
+fn attacked_squares(board: &Board, color: Color) -> BitBoard {
+    let mut result = EMPTY;
+    for sq in *board.color_combined(color) {
+        let piece = board.piece_on(sq).unwrap();
+        result |= piece_attacks(piece, sq, color, *board.combined());
+    }
+    result
+}
+
+fn foreach_pawn_structure_feature<F>(board: &Board, pawns: &PawnStructure, c: Color, mut f: F)
+where
+    F: FnMut(usize),
+{
+    let bb = |x: BitBoard| x & board.color_combined(c);
+    for sq in bb(pawns.passed) {
+        let rank = sq.get_rank().to_index();
+        let rank = if c == Color::White { rank } else { 7 - rank };
+        f(PASSED_PAWN_RANK_2 + rank - 1);
+    }
+    for (set, feat) in &[
+        (pawns.isolated, ISOLATED_PAWN),
+        (pawns.doubled, DOUBLED_PAWN),
+        (pawns.backward, BACKWARD_PAWN),
+        (pawns.connected, CONNECTED_PAWN),
+    ] {
+        for _ in bb(*set) {
+            f(*feat);
+        }
+    }
+}
+
+/// Pawn shield, open files and attacks around the king of `c`, and the
+/// checks the opponent could give on squares `c` does not defend.
+fn foreach_king_safety_feature<F>(board: &Board, c: Color, mut f: F)
+where
+    F: FnMut(usize),
+{
+    let king = board.king_square(c);
+    let occupied = *board.combined();
+    let our_pawns = board.pieces(Piece::Pawn) & board.color_combined(c);
+    let rank = king.get_rank().to_index() as i32;
+    let forward = if c == Color::White { 1 } else { -1 };
+    let mut shield_ranks = EMPTY;
+    for dist in 1..3 {
+        let r = rank + forward * dist;
+        if 0 <= r && r < NUM_RANKS as i32 {
+            shield_ranks |= get_rank(Rank::from_index(r as usize));
+        }
+    }
+    let king_files = get_file(king.get_file()) | get_adjacent_files(king.get_file());
+    let shield = (our_pawns & king_files & shield_ranks).popcnt();
+    f(KING_SHIELD_PAWNS_0 + min(shield, 3) as usize);
+
+    let mut open_files = 0;
+    for file in 0..NUM_FILES {
+        let file = get_file(File::from_index(file));
+        if (file & king_files).0 != 0 && (file & our_pawns).0 == 0 {
+            open_files += 1;
+        }
+    }
+    f(KING_OPEN_FILES_0 + open_files);
+
+    let zone = get_king_moves(king) | BitBoard::from_square(king);
+    let defended = attacked_squares(board, c);
+    let mut attackers = 0;
+    let mut attacked_by = [false; NUM_ROLES];
+    let mut safe_check_by = [false; NUM_ROLES];
+    for sq in *board.color_combined(!c) {
+        let piece = board.piece_on(sq).unwrap();
+        if (piece_attacks(piece, sq, !c, occupied) & zone).0 != 0 {
+            attackers += 1;
+            attacked_by[piece.to_index()] = true;
+        }
+        if piece == Piece::King {
+            continue;
+        }
+        let mut targets = piece_attacks(piece, sq, !c, occupied);
+        if piece == Piece::Pawn {
+            targets &= *board.color_combined(c);
+            targets |= get_pawn_quiets(sq, !c, occupied);
+        }
+        let checks = piece_attacks(piece, king, c, occupied);
+        if (targets & checks & !board.color_combined(!c) & !defended).0 != 0 {
+            safe_check_by[piece.to_index()] = true;
         }
     }
+    f(KING_ZONE_ATTACKERS_0 + min(attackers, 4));
+    for (i, &piece) in ALL_PIECES.iter().enumerate() {
+        if attacked_by[i] {
+            f(KING_ZONE_ATTACKED_BY_PAWN + piece.to_index());
+        }
+        if safe_check_by[i] {
+            f(SAFE_CHECK_BY_PAWN + piece.to_index());
+        }
+    }
+}
+
+fn foreach_square_pattern<F>(p: Phase, color: Color, piece: Piece, sq: Square, f: &mut F)
+where
+    F: FnMut(usize, f32),
+{
+    for &is_rank in &[false, true] {
+        if piece != Piece::Pawn
+            && piece != Piece::Rook
+            && piece != Piece::King
+            && is_rank
+            && sq.get_rank() != Rank::First
+            && sq.get_rank() != Rank::Eighth
+        {
+            continue;
+        }
+        if piece != Piece::Pawn && piece != Piece::King && piece != Piece::Knight && !is_rank {
+            continue;
+        }
+        f(
+            pattern_index(p, FullPiece { color, role: piece }, sq, is_rank),
+            1.,
+        );
+    }
+}
+
+fn foreach_pattern_feature<F>(board: &Board, p: Phase, mut f: F)
+where
+    F: FnMut(usize, f32),
+{
+    for sq in *board.combined() {
+        let pc = full_piece_on(board, sq).unwrap();
+        foreach_square_pattern(p, pc.color, pc.role, sq, &mut f);
+    }
     if p != Phase::Endgame {
         for file in 0..7 {
             for rank in 0..7 {
                 let pattern =
                     extract_2x2_pattern(board, File::from_index(file), Rank::from_index(rank));
-                f(index_2x2_pattern(&pattern), 1);
+                f(index_2x2_pattern(&pattern), 1.);
             }
         }
     }
 }
 
+fn foreach_feature<F>(state: &State, mut f: F)
+where
+    F: FnMut(usize, f32),
+{
+    foreach_dense_feature(state, &mut f);
+    let weights = phase_weights(state.board());
+    for &p in &PHASES {
+        let w = weights[p as usize];
+        if w != 0. {
+            foreach_pattern_feature(state.board(), p, |i, v| f(i, v * w));
+        }
+    }
+}
+
 pub fn featurize(state: &State) -> FeatureVec {
-    let mut arr = [0u8; NUM_DENSE_FEATURES];
+    let mut arr = [0f32; NUM_DENSE_FEATURES];
     let mut patterns = Vec::with_capacity(MAX_PATTERNS_IN_POSITION);
     foreach_feature(state, |i, v| {
-        assert_eq!(v, 1);
         assert!(i < NUM_FEATURES);
         if i < NUM_DENSE_FEATURES {
             arr[i] += v;
         } else {
-            patterns.push(i as usize);
+            patterns.push((i, v));
         }
     });
-    assert!(patterns.len() <= MAX_PATTERNS_IN_POSITION);
+    assert!(patterns.len() <= NUM_PHASES * MAX_PATTERNS_IN_POSITION);
     FeatureVec {
         arr: arr.to_vec(),
         patterns,
     }
 }
 
+fn add_coef(result: &mut [f32; NUM_OUTCOMES], i: usize, sign: f32) {
+    if i < NUM_MODEL_FEATURES {
+        for (x, c) in result.iter_mut().zip(COEF[i].iter()) {
+            *x += sign * c;
+        }
+    }
+}
+
+/// Sums of the model coefficients of the piece/square and 2x2 pattern features.
+/// `State::make_move` keeps it up to date by only revisiting the changed squares.
+#[derive(Clone)]
+pub struct Accumulator {
+    patterns: [[f32; NUM_OUTCOMES]; NUM_PHASES],
+    patterns_2x2: [f32; NUM_OUTCOMES],
+}
+
+impl Accumulator {
+    pub fn new(board: &Board) -> Self {
+        let mut acc = Accumulator {
+            patterns: [[0.; NUM_OUTCOMES]; NUM_PHASES],
+            patterns_2x2: [0.; NUM_OUTCOMES],
+        };
+        for &p in &PHASES {
+            let result = &mut acc.patterns[p as usize];
+            foreach_pattern_feature(board, p, |i, _| {
+                if i < NUM_DENSE_FEATURES + NUM_PATTERNS {
+                    add_coef(result, i, 1.);
+                }
+            });
+        }
+        foreach_pattern_feature(board, Phase::Midgame, |i, _| {
+            if i >= NUM_DENSE_FEATURES + NUM_PATTERNS {
+                add_coef(&mut acc.patterns_2x2, i, 1.);
+            }
+        });
+        acc
+    }
+
+    pub fn update(&mut self, before: &Board, after: &Board) {
+        let mut changed = EMPTY;
+        for &color in &[Color::White, Color::Black] {
+            for &piece in ALL_PIECES.iter() {
+                changed |= (before.pieces(piece) & before.color_combined(color))
+                    ^ (after.pieces(piece) & after.color_combined(color));
+            }
+        }
+        let mut windows = EMPTY;
+        for sq in changed {
+            for (board, sign) in &[(before, -1.), (after, 1.)] {
+                if let Some(pc) = full_piece_on(board, sq) {
+                    for &p in &PHASES {
+                        let result = &mut self.patterns[p as usize];
+                        foreach_square_pattern(p, pc.color, pc.role, sq, &mut |i, _| {
+                            add_coef(result, i, *sign)
+                        });
+                    }
+                }
+            }
+            let file = sq.get_file().to_index();
+            let rank = sq.get_rank().to_index();
+            for f in file.saturating_sub(1)..min(file + 1, 7) {
+                for r in rank.saturating_sub(1)..min(rank + 1, 7) {
+                    windows |= BitBoard::from_square(Square::make_square(
+                        Rank::from_index(r),
+                        File::from_index(f),
+                    ));
+                }
+            }
+        }
+        for sq in windows {
+            for (board, sign) in &[(before, -1.), (after, 1.)] {
+                let pattern = extract_2x2_pattern(board, sq.get_file(), sq.get_rank());
+                add_coef(&mut self.patterns_2x2, index_2x2_pattern(&pattern), *sign);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn approx_eq(&self, other: &Accumulator) -> bool {
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-4);
+        self.patterns
+            .iter()
+            .zip(other.patterns.iter())
+            .all(|(a, b)| close(a, b))
+            && close(&self.patterns_2x2, &other.patterns_2x2)
+    }
+}
+
 pub struct Model;
 
 impl Model {
@@ -299,15 +516,15 @@ impl Model {
     }
     pub fn predict(&self, state: &State) -> [f32; NUM_OUTCOMES] {
         let mut result = [0f32; NUM_OUTCOMES];
-        foreach_feature(state, |i, _| {
-            if i < NUM_MODEL_FEATURES {
-                #[allow(clippy::needless_range_loop)]
-                for j in 0..NUM_OUTCOMES {
-                    // result[j] += COEF[i][j] * (v as f32);
-                    result[j] += COEF[i][j];
-                }
+        foreach_dense_feature(state, |i, v| add_coef(&mut result, i, v));
+        let weights = phase_weights(state.board());
+        let acc = state.accumulator();
+        for (j, x) in result.iter_mut().enumerate() {
+            for &p in &PHASES {
+                *x += weights[p as usize] * acc.patterns[p as usize][j];
             }
-        });
+            *x += weights[Phase::Midgame as usize] * acc.patterns_2x2[j];
+        }
         for x in &mut result {
             *x = x.exp();
         }
@@ -326,18 +543,26 @@ impl Model {
     }
 }
 
-fn phase(s: &State) -> Phase {
-    if s.is_endgame() {
-        Phase::Endgame
-    } else {
-        Phase::Midgame
+const PHASES: [Phase; NUM_PHASES] = [Phase::Midgame, Phase::Endgame];
+
+/// How much of the midgame and endgame coefficient sets apply to the position,
+/// interpolated by the non-pawn material left on the board.
+fn phase_weights(board: &Board) -> [f32; NUM_PHASES] {
+    let mut material = 0;
+    for (&piece, &w) in ALL_PIECES.iter().zip(PHASE_PIECE_WEIGHTS.iter()) {
+        material += w * board.pieces(piece).popcnt();
     }
+    let midgame = min(material, MAX_PHASE) as f32 / MAX_PHASE as f32;
+    let mut weights = [0.; NUM_PHASES];
+    weights[Phase::Midgame as usize] = midgame;
+    weights[Phase::Endgame as usize] = 1. - midgame;
+    weights
 }
 
 pub fn name_feature(fidx: usize) -> String {
     assert!(fidx < NUM_DENSE_FEATURES);
     for (c, side) in ["OUR", "OPPONENT"].iter().enumerate() {
-        for p in &[Phase::Midgame, Phase::Endgame] {
+        for p in &PHASES {
             for (idx, name) in INDEX_NAMES.iter().enumerate() {
                 if feature_index(c, *p, idx) == fidx {
                     return format!("{}_{:?}_{}", side, p, name).to_lowercase();
@@ -348,6 +573,81 @@ pub fn name_feature(fidx: usize) -> String {
     unreachable!()
 }
 
+fn piece_char(elt: usize) -> char {
+    if elt == NUM_PIECES {
+        return '.';
+    }
+    let c = b"pnbrqk"[elt % NUM_ROLES] as char;
+    if elt / NUM_ROLES == Color::White as usize {
+        c.to_ascii_uppercase()
+    } else {
+        c
+    }
+}
+
+/// Names any feature index, including the piece/square and 2x2 patterns.
+pub fn describe_feature(fidx: usize) -> String {
+    assert!(fidx < NUM_FEATURES);
+    if fidx < NUM_DENSE_FEATURES {
+        name_feature(fidx)
+    } else if fidx < NUM_DENSE_FEATURES + NUM_PATTERNS {
+        let x = fidx - NUM_DENSE_FEATURES;
+        let rr = x % (NUM_RANKS + NUM_FILES);
+        let x = x / (NUM_RANKS + NUM_FILES);
+        let pc = x % NUM_PIECES;
+        let ph = if x / NUM_PIECES == Phase::Midgame as usize {
+            Phase::Midgame
+        } else {
+            Phase::Endgame
+        };
+        let place = if rr < NUM_RANKS {
+            format!("rank_{}", rr + 1)
+        } else {
+            format!("file_{}", (b'a' + (rr - NUM_RANKS) as u8) as char)
+        };
+        format!(
+            "{:?}_{}_{:?}_{}",
+            ph,
+            if pc / NUM_ROLES == Color::White as usize {
+                "white"
+            } else {
+                "black"
+            },
+            ALL_PIECES[pc % NUM_ROLES],
+            place
+        )
+        .to_lowercase()
+    } else {
+        let mut x = fidx - NUM_DENSE_FEATURES - NUM_PATTERNS;
+        let mut elts = [0; 4];
+        for e in elts.iter_mut().rev() {
+            *e = x % NUM_2X2_ELTS;
+            x /= NUM_2X2_ELTS;
+        }
+        let c: Vec<char> = elts.iter().map(|&e| piece_char(e)).collect();
+        format!("2x2_{}{}/{}{}", c[2], c[3], c[0], c[1])
+    }
+}
+
+/// Every active feature of the position, with its coefficients multiplied by
+/// its phase-weighted value. Columns are win, loss and draw for the side to move.
+pub fn explain(state: &State) -> Vec<(String, [f32; NUM_OUTCOMES])> {
+    let mut counts = Vec::new();
+    foreach_feature(state, |i, v| counts.push((i, v)));
+    counts.sort_unstable_by_key(|x| x.0);
+    let mut result: Vec<(String, [f32; NUM_OUTCOMES])> = Vec::new();
+    let mut last = None;
+    for (i, v) in counts {
+        if last != Some(i) {
+            result.push((describe_feature(i), [0.; NUM_OUTCOMES]));
+            last = Some(i);
+        }
+        let contribution = &mut result.last_mut().unwrap().1;
+        add_coef(contribution, i, v);
+    }
+    result
+}
+
 fn full_piece_on(board: &Board, sq: Square) -> Option<FullPiece> {
     let role = board.piece_on(sq)?;
     let color = if (board.color_combined(Color::White) & BitBoard::from_square(sq)).0 != 0 {
@@ -374,3 +674,45 @@ fn extract_2x2_pattern(board: &Board, file: File, rank: Rank) -> [usize; 4] {
             .unwrap_or(NUM_PIECES),
     ]
 }
+
+#[cfg(test)]
+#[test]
+fn test_king_safety() {
+    use std::str::FromStr;
+    let board = Board::from_str("6k1/8/8/6n1/7q/8/5P1P/6K1 w - - 0 1").unwrap();
+    let mut feats = Vec::new();
+    foreach_king_safety_feature(&board, Color::White, |i| feats.push(i));
+    feats.sort_unstable();
+    let mut expected = vec![
+        KING_SHIELD_PAWNS_2,
+        KING_OPEN_FILES_1,
+        KING_ZONE_ATTACKERS_1,
+        KING_ZONE_ATTACKED_BY_QUEEN,
+        SAFE_CHECK_BY_KNIGHT,
+        SAFE_CHECK_BY_QUEEN,
+    ];
+    expected.sort_unstable();
+    assert_eq!(feats, expected);
+}
+
+#[cfg(test)]
+#[test]
+fn test_predict_matches_explain() {
+    // queens off: both phases apply
+    let state =
+        State::from_fen("r2r2k1/pp3ppp/2n1bn2/8/8/2N1BN2/PP3PPP/R2R2K1 w - - 0 15").unwrap();
+    let weights = phase_weights(state.board());
+    assert!(weights[Phase::Midgame as usize] > 0. && weights[Phase::Endgame as usize] > 0.);
+
+    let mut logits = [0f32; NUM_OUTCOMES];
+    for (_, contribution) in explain(&state) {
+        for (x, y) in logits.iter_mut().zip(contribution.iter()) {
+            *x += y;
+        }
+    }
+    let total: f32 = logits.iter().map(|x| x.exp()).sum();
+    let predicted = Model::new().predict(&state);
+    for (p, x) in predicted.iter().zip(logits.iter()) {
+        assert!((p - x.exp() / total).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,158 @@
+extern crate rand;
+
+use self::rand::{Rng, SeedableRng, XorShiftRng};
+
+use atomics::{AtomicBool, Ordering};
+use mcts::MCTSManager;
+use search::{GooseMCTS, SCALE};
+use state::Move;
+use std::cmp::max;
+
+const C_VISIT: f32 = 50.;
+const C_SCALE: f32 = 1.;
+
+fn gumbel(rng: &mut XorShiftRng) -> f32 {
+    let u: f32 = rng.gen_range(1e-7, 1.);
+    -(-u.ln()).ln()
+}
+
+/// The scale of the value bonus, which grows with the visits of the most
+/// visited root move.
+fn sigma(manager: &MCTSManager<GooseMCTS>) -> f32 {
+    let root = manager.tree().root_node();
+    let max_visits = root.moves().map(|m| m.visits()).max().unwrap_or(0);
+    (C_VISIT + max_visits as f32) * C_SCALE
+}
+
+/// The average reward of each root move mapped to [0, 1], if it was visited.
+fn values(manager: &MCTSManager<GooseMCTS>) -> Vec<Option<f32>> {
+    let root = manager.tree().root_node();
+    root.moves()
+        .map(|m| m.average_reward().map(|r| (r / SCALE + 1.) / 2.))
+        .collect()
+}
+
+/// The noisy prior logit of each root move plus its value bonus.
+fn scores(manager: &MCTSManager<GooseMCTS>, noisy_logits: &[f32]) -> Vec<f32> {
+    let sigma = sigma(manager);
+    values(manager)
+        .iter()
+        .zip(noisy_logits)
+        .map(|(q, logit)| logit + sigma * q.unwrap_or(0.5))
+        .collect()
+}
+
+/// The improved policy of the paper, the softmax of each root move's prior
+/// logit plus its value bonus, in root move order. Unvisited moves are valued
+/// at the prior-weighted average value of the visited ones.
+pub fn improved_policy(manager: &MCTSManager<GooseMCTS>) -> Vec<f32> {
+    let priors: Vec<f32> = manager
+        .tree()
+        .root_node()
+        .moves()
+        .map(|m| *m.move_evaluation())
+        .collect();
+    let values = values(manager);
+    let (sum, weight) = values
+        .iter()
+        .zip(&priors)
+        .filter_map(|(q, p)| q.map(|q| (p * q, *p)))
+        .fold((0., 0.), |(s, w), (x, p)| (s + x, w + p));
+    let unvisited = if weight > 0. { sum / weight } else { 0.5 };
+    let sigma = sigma(manager);
+    let logits: Vec<f32> = priors
+        .iter()
+        .zip(&values)
+        .map(|(p, q)| p.max(1e-30).ln() + sigma * q.unwrap_or(unvisited))
+        .collect();
+    let max = logits.iter().cloned().fold(f32::MIN, f32::max);
+    let exps: Vec<f32> = logits.iter().map(|x| (x - max).exp()).collect();
+    let total: f32 = exps.iter().sum();
+    exps.iter().map(|x| x / total).collect()
+}
+
+/// Gumbel root search ("Policy improvement by planning with Gumbel", Danihelka
+/// et al.). It samples up to `moves` root moves without replacement by adding
+/// Gumbel noise to the prior logits, then spreads `nodes` playouts over them by
+/// sequential halving, keeping the better half by noisy logit plus value bonus
+/// after each phase. Returns the last move left, or the best one so far if
+/// `stop` is set or the tree fills up, and makes it the tree's chosen root move.
+pub fn gumbel_search(
+    manager: &MCTSManager<GooseMCTS>,
+    nodes: u32,
+    moves: usize,
+    num_threads: usize,
+    seed: u32,
+    stop: &AtomicBool,
+) -> Option<Move> {
+    let mut rng: XorShiftRng = SeedableRng::from_seed([seed, 5, 6, 7]);
+    let noisy_logits: Vec<f32> = manager
+        .tree()
+        .root_node()
+        .moves()
+        .map(|m| m.move_evaluation().max(1e-30).ln() + gumbel(&mut rng))
+        .collect();
+    if noisy_logits.is_empty() {
+        return None;
+    }
+
+    let by_score =
+        |scores: &[f32], a: &usize, b: &usize| scores[*b].partial_cmp(&scores[*a]).unwrap();
+    let mut candidates: Vec<usize> = (0..noisy_logits.len()).collect();
+    candidates.sort_by(|a, b| by_score(&noisy_logits, a, b));
+    candidates.truncate(max(1, moves));
+
+    let phases = max(1, (candidates.len() as f32).log2().ceil() as usize);
+    let mut tlds = manager.thread_data(max(1, num_threads));
+    let mut budget = nodes as usize;
+    for phase in 0..phases {
+        let visits = max(1, budget / ((phases - phase) * candidates.len()));
+        let schedule: Vec<usize> = (0..visits)
+            .flat_map(|_| candidates.iter().cloned())
+            .collect();
+        let grown = manager.playout_schedule_parallel(&schedule, &mut tlds, stop);
+        budget = budget.saturating_sub(schedule.len());
+
+        let scores = scores(manager, &noisy_logits);
+        candidates.sort_by(|a, b| by_score(&scores, a, b));
+        if !grown || stop.load(Ordering::SeqCst) {
+            break;
+        }
+        candidates.truncate(candidates.len().div_ceil(2));
+    }
+
+    manager.tree().choose_root_move(Some(candidates[0]));
+    let root = manager.tree().root_node();
+    root.moves().nth(candidates[0]).map(|m| *m.get_move())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use search::{to_uci, Search};
+    use search_tree::empty_previous_table;
+    use state::State;
+
+    #[test]
+    fn halving() {
+        let state = State::from_fen("4k3/8/8/3q4/8/8/7P/3QK3 w - - 0 1").unwrap();
+        let manager = Search::create_manager(state, empty_previous_table());
+        let mov = gumbel_search(&manager, 400, 8, 1, 1, &AtomicBool::new(false)).unwrap();
+        assert_eq!(to_uci(mov), "d1d5");
+        assert_eq!(manager.best_move(), Some(mov));
+
+        let root = manager.tree().root_node();
+        let visits: Vec<u64> = root.moves().map(|m| m.visits()).collect();
+        assert!(visits.iter().sum::<u64>() >= 400);
+        assert_eq!(visits.iter().filter(|&&v| v > 0).count(), 8);
+        let best = root.moves().find(|m| *m.get_move() == mov).unwrap();
+        assert_eq!(best.visits(), *visits.iter().max().unwrap());
+
+        let policy = improved_policy(&manager);
+        assert!((policy.iter().sum::<f32>() - 1.).abs() < 1e-4);
+        let most_likely = (0..policy.len())
+            .max_by(|&a, &b| policy[a].partial_cmp(&policy[b]).unwrap())
+            .unwrap();
+        assert_eq!(root.moves().nth(most_likely).unwrap().get_move(), &mov);
+    }
+}
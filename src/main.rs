@@ -16,10 +16,12 @@ extern crate smallvec;
 
 mod arena;
 mod atomics;
+mod eval_cache;
 mod mcts;
 mod options;
 mod search_tree;
 mod tablebase;
+mod time_control;
 mod transposition_table;
 mod tree_policy;
 
@@ -27,12 +29,36 @@ mod args;
 mod evaluation;
 mod features;
 mod features_common;
+mod gumbel;
+mod mate_search;
+mod network;
+mod pawn_structure;
 mod policy_features;
+mod see;
 mod search;
+mod selfplay;
 mod state;
+mod train_data;
+mod trainer;
 mod training;
 mod uci;
 
+fn train_threads(threads: usize) -> usize {
+    if threads > 0 {
+        threads
+    } else {
+        std::thread::available_parallelism().map_or(1, |x| x.get())
+    }
+}
+
+fn split_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.into())
+        .collect()
+}
+
 fn main() {
     args::init();
     let options = args::options();
@@ -40,7 +66,69 @@ fn main() {
     pretty_env_logger::init();
 
     if let Some(ref train_pgn) = options.train_pgn {
-        training::train(train_pgn, &options.train_output_path, options.policy);
+        let config = training::TrainingConfig {
+            format: options.data_format,
+            threads: train_threads(options.train_threads),
+            min_elo: options.train_min_elo,
+            policy_min_elo: options.train_policy_min_elo,
+            samples: options.train_samples,
+            skip_plies: options.train_skip_plies,
+            min_frequency: options.train_min_frequency,
+            quiet_only: options.train_quiet_only,
+            tb_labels: options.train_syzygy_path.as_ref().map(|path| {
+                tablebase::set_tablebase_directory(path);
+                options.train_tb_labels
+            }),
+            eval_weight: options.train_eval_weight,
+            seed: options.seed,
+            time_controls: split_list(&options.train_time_controls),
+            terminations: split_list(&options.train_terminations),
+            variants: split_list(&options.train_variants),
+        };
+        training::train(train_pgn, &options.train_output_path, options.policy, &config);
+    } else if let Some(ref in_path) = options.convert {
+        let kind = if options.policy {
+            train_data::DataKind::Policy
+        } else {
+            train_data::DataKind::Value
+        };
+        let rows = train_data::convert(in_path, &options.train_output_path, kind).expect("convert");
+        println!("{} rows", rows);
+    } else if options.selfplay_games > 0 {
+        options::set_dirichlet_noise(
+            Some((options.noise_alpha * 1000.).round() as usize),
+            Some((options.noise_epsilon * 100.).round() as usize),
+        );
+        options::set_noise_seed(options.seed);
+        options::set_gumbel_moves(options.gumbel_moves);
+        let selfplay_options = selfplay::SelfPlayOptions {
+            games: options.selfplay_games,
+            nodes: options.selfplay_nodes,
+            random_plies: options.selfplay_random_plies,
+            temperature_plies: options.selfplay_temperature_plies,
+            temperature: options.selfplay_temperature,
+            seed: options.seed,
+            format: options.data_format,
+        };
+        selfplay::self_play(
+            &selfplay_options,
+            &options.train_output_path,
+            &options.fit_key_path,
+            &options.selfplay_pgn_path,
+        );
+    } else if let Some(ref data) = options.fit_data {
+        let fit_options = trainer::FitOptions {
+            epochs: options.fit_epochs,
+            learning_rate: options.fit_learning_rate,
+            l2: options.fit_l2,
+        };
+        if options.policy {
+            let model = options.fit_model_path.as_ref().map_or("policy_model", |x| x);
+            trainer::fit_policy(data, &options.fit_key_path, model, &fit_options);
+        } else {
+            let model = options.fit_model_path.as_ref().map_or("model", |x| x);
+            trainer::fit_value(data, model, &fit_options);
+        }
     } else {
         info!("Init.");
         uci::main(options.extra.clone());
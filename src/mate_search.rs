@@ -0,0 +1,167 @@
+use chess::*;
+
+/// A forced result for the side to move.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MateProof {
+    Win,
+    Loss,
+}
+
+/// Whether `attacker` might have a move that gives check, from bitboards
+/// alone: a piece that can reach a square attacking the enemy king, a piece
+/// that blocks one of its own sliders, or a pawn about to promote. It can
+/// report checks that turn out to be illegal, and misses only checks given
+/// by castling or en passant.
+fn may_check(board: &Board, attacker: Color) -> bool {
+    let king = board.king_square(!attacker);
+    let occupied = *board.combined();
+    let ours = *board.color_combined(attacker);
+    let pieces = |piece| board.pieces(piece) & ours;
+    let diagonal = get_bishop_moves(king, occupied);
+    let line = get_rook_moves(king, occupied);
+
+    let mut reach = EMPTY;
+    for sq in pieces(Piece::Knight) {
+        reach |= get_knight_moves(sq) & get_knight_moves(king);
+    }
+    for sq in pieces(Piece::Bishop) {
+        reach |= get_bishop_moves(sq, occupied) & diagonal;
+    }
+    for sq in pieces(Piece::Rook) {
+        reach |= get_rook_moves(sq, occupied) & line;
+    }
+    for sq in pieces(Piece::Queen) {
+        reach |=
+            (get_bishop_moves(sq, occupied) | get_rook_moves(sq, occupied)) & (diagonal | line);
+    }
+    if reach & !ours != EMPTY {
+        return true;
+    }
+
+    let mut pawn_origins = EMPTY;
+    for sq in get_pawn_attacks(king, !attacker, !EMPTY) {
+        pawn_origins |= get_king_moves(sq);
+        let double_push = sq.backward(attacker).and_then(|x| x.backward(attacker));
+        if let Some(from) = double_push {
+            pawn_origins |= BitBoard::from_square(from);
+        }
+    }
+    if pawn_origins & pieces(Piece::Pawn) != EMPTY {
+        return true;
+    }
+
+    let seventh = match attacker {
+        Color::White => Rank::Seventh,
+        Color::Black => Rank::Second,
+    };
+    if pieces(Piece::Pawn) & get_rank(seventh) != EMPTY {
+        return true;
+    }
+
+    let diagonal_sliders = pieces(Piece::Bishop) | pieces(Piece::Queen);
+    let line_sliders = pieces(Piece::Rook) | pieces(Piece::Queen);
+    for blocker in (diagonal | line) & ours {
+        let occupied = occupied ^ BitBoard::from_square(blocker);
+        if get_bishop_moves(king, occupied) & diagonal_sliders != EMPTY
+            || get_rook_moves(king, occupied) & line_sliders != EMPTY
+        {
+            return true;
+        }
+    }
+    false
+}
+
+fn checks(board: &Board) -> impl Iterator<Item = Board> + '_ {
+    MoveGen::new_legal(board)
+        .map(move |m| board.make_move_new(m))
+        .filter(|b| b.checkers().popcnt() > 0)
+}
+
+fn mates_in_one(board: &Board) -> bool {
+    may_check(board, board.side_to_move())
+        && checks(board).any(|b| b.status() == BoardStatus::Checkmate)
+}
+
+fn mates_in_two(board: &Board) -> bool {
+    may_check(board, board.side_to_move()) && checks(board).any(|b| mated_by(&b, mates_in_one))
+}
+
+/// The side to move has legal moves and each lets the opponent play a mate
+/// found by `mates`. Unless in check, this is only tried when the opponent
+/// may have a check to give.
+fn mated_by(board: &Board, mates: fn(&Board) -> bool) -> bool {
+    if board.checkers().popcnt() == 0 && !may_check(board, !board.side_to_move()) {
+        return false;
+    }
+    let mut moves = MoveGen::new_legal(board).peekable();
+    moves.peek().is_some() && moves.all(|m| mates(&board.make_move_new(m)))
+}
+
+/// Looks for mates within `plies` plies (up to 4), following only checks for
+/// the mating side. Positions where neither side can give check are
+/// dismissed from bitboards, without generating moves.
+pub fn prove(board: &Board, plies: usize) -> Option<MateProof> {
+    if plies >= 1 && mates_in_one(board) {
+        return Some(MateProof::Win);
+    }
+    if plies >= 2 && mated_by(board, mates_in_one) {
+        return Some(MateProof::Loss);
+    }
+    if plies >= 3 && mates_in_two(board) {
+        return Some(MateProof::Win);
+    }
+    if plies >= 4 && mated_by(board, mates_in_two) {
+        return Some(MateProof::Loss);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn prove_fen(fen: &str, plies: usize) -> Option<MateProof> {
+        prove(&Board::from_str(fen).unwrap(), plies)
+    }
+
+    #[test]
+    fn mates() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(prove_fen(start, 3), None);
+
+        let back_rank = "6k1/5ppp/8/8/8/8/8/R6K w - - 0 1";
+        assert_eq!(prove_fen(back_rank, 0), None);
+        assert_eq!(prove_fen(back_rank, 1), Some(MateProof::Win));
+
+        let ladder = "8/6k1/R7/8/8/8/8/1R4K1 w - - 0 1";
+        assert_eq!(prove_fen(ladder, 2), None);
+        assert_eq!(prove_fen(ladder, 3), Some(MateProof::Win));
+
+        let after_check = "8/1R4k1/R7/8/8/8/8/6K1 b - - 1 1";
+        assert_eq!(prove_fen(after_check, 1), None);
+        assert_eq!(prove_fen(after_check, 2), Some(MateProof::Loss));
+
+        // not in check, but the only move walks into a back rank mate
+        let quiet = "7k/6p1/6P1/8/8/8/8/R1K5 b - - 0 1";
+        assert_eq!(prove_fen(quiet, 1), None);
+        assert_eq!(prove_fen(quiet, 2), Some(MateProof::Loss));
+
+        let mated_in_two = "3k4/1R6/6Q1/8/4p3/3pK3/8/8 b - - 0 1";
+        assert_eq!(prove_fen(mated_in_two, 3), None);
+        assert_eq!(prove_fen(mated_in_two, 4), Some(MateProof::Loss));
+
+        let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+        assert_eq!(prove_fen(stalemate, 4), None);
+    }
+
+    #[test]
+    fn quiet_positions_have_no_checks() {
+        let board = Board::default();
+        assert!(!may_check(&board, Color::White));
+        assert!(!may_check(&board, Color::Black));
+        let discovered = Board::from_str("4k3/8/8/8/4N3/8/8/4R1K1 w - - 0 1").unwrap();
+        assert!(may_check(&discovered, Color::White));
+        assert!(!may_check(&discovered, Color::Black));
+    }
+}
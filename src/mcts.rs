@@ -114,6 +114,8 @@ use std::sync::Arc;
 use std::thread::JoinHandle;
 use std::time::Duration;
 
+const MONITOR_INTERVAL: Duration = Duration::from_millis(10);
+
 pub trait MCTS: Sized + Sync {
     type State: GameState + Sync;
     type Eval: Evaluator<Self>;
@@ -243,6 +245,17 @@ pub trait Evaluator<Spec: MCTS>: Sync {
         evaluation: &Self::StateEvaluation,
         player: &Player<Spec>,
     ) -> i64;
+
+    /// A proven evaluation of a new non-root node, which then becomes a leaf.
+    /// The default implementation proves nothing.
+    fn proven_evaluation(&self, _state: &Spec::State) -> Option<Self::StateEvaluation> {
+        None
+    }
+
+    /// Extra lines for `SearchTree::diagnose`. The default implementation returns nothing.
+    fn diagnose(&self) -> String {
+        String::new()
+    }
 }
 
 pub struct MCTSManager<Spec: MCTS> {
@@ -323,12 +336,67 @@ where
             threads,
         }
     }
+    unsafe fn spawn_monitor_thread<F>(
+        &self,
+        stop_signal: Arc<AtomicBool>,
+        mut monitor: F,
+    ) -> JoinHandle<()>
+    where
+        F: FnMut(&SearchTree<Spec>) -> bool + Send,
+    {
+        let search_tree = &self.search_tree;
+        crossbeam::spawn_unsafe(move || {
+            while !stop_signal.load(Ordering::SeqCst) {
+                std::thread::sleep(MONITOR_INTERVAL);
+                if !monitor(search_tree) {
+                    break;
+                }
+            }
+        })
+    }
+    /// Like `into_playout_parallel_async`, with one more thread that calls `monitor`
+    /// on the tree every `MONITOR_INTERVAL` until it returns false or the search halts.
+    pub fn into_playout_parallel_async_monitored<F>(
+        self,
+        num_threads: usize,
+        monitor: F,
+    ) -> AsyncSearchOwned<Spec>
+    where
+        F: FnMut(&SearchTree<Spec>) -> bool + Send,
+    {
+        let mut search = self.into_playout_parallel_async(num_threads);
+        let thread = {
+            let manager = search.manager.as_ref().unwrap();
+            unsafe { manager.spawn_monitor_thread(search.stop_signal.clone(), monitor) }
+        };
+        search.threads.push(thread);
+        search
+    }
+    /// Runs `job` in a background thread, handing it the stop signal that
+    /// halting the returned search sets.
+    pub fn into_job_async<F>(self, job: F) -> AsyncSearchOwned<Spec>
+    where
+        F: FnOnce(&MCTSManager<Spec>, &AtomicBool) + Send,
+    {
+        let mut search: AsyncSearchOwned<Spec> = self.into();
+        let thread = {
+            let manager: &MCTSManager<Spec> = search.manager.as_ref().unwrap();
+            let stop_signal = search.stop_signal.clone();
+            unsafe { crossbeam::spawn_unsafe(move || job(manager, &stop_signal)) }
+        };
+        search.threads.push(thread);
+        search
+    }
     pub fn playout_parallel_for(&mut self, duration: Duration, num_threads: usize) {
         let search = self.playout_parallel_async(num_threads);
         std::thread::sleep(duration);
         search.halt();
     }
     pub fn playout_n_parallel(&mut self, n: u32, num_threads: usize) {
+        self.playout_n_parallel_until(n, num_threads, &AtomicBool::new(false));
+    }
+    /// Like `playout_n_parallel`, but returns early once `stop` is set.
+    pub fn playout_n_parallel_until(&self, n: u32, num_threads: usize, stop: &AtomicBool) {
         if n == 0 {
             return;
         }
@@ -341,7 +409,7 @@ where
                     let mut tld = ThreadData::create(search_tree);
                     loop {
                         let count = counter.fetch_sub(1, Ordering::SeqCst);
-                        if count <= 0 {
+                        if count <= 0 || stop.load(Ordering::SeqCst) {
                             break;
                         }
                         search_tree.playout(&mut tld);
@@ -350,6 +418,48 @@ where
             }
         });
     }
+    /// Thread data for `num_threads` threads, to be reused across calls to
+    /// `playout_schedule_parallel` so that each does not take fresh arena chunks.
+    pub fn thread_data<'a>(&'a self, num_threads: usize) -> Vec<ThreadData<'a, Spec>> {
+        (0..num_threads)
+            .map(|_| ThreadData::create(&self.search_tree))
+            .collect()
+    }
+    /// Runs one playout through each root move index in `schedule`, with a
+    /// thread for each of `tlds`, until `stop` is set. Returns false if the
+    /// tree is full.
+    pub fn playout_schedule_parallel<'a>(
+        &'a self,
+        schedule: &[usize],
+        tlds: &mut [ThreadData<'a, Spec>],
+        stop: &AtomicBool,
+    ) -> bool
+    where
+        TreePolicyThreadData<Spec>: Send,
+        Spec::ExtraThreadData: Send,
+    {
+        assert!(!tlds.is_empty());
+        let next = AtomicUsize::new(0);
+        let full = AtomicBool::new(false);
+        let search_tree = &self.search_tree;
+        crossbeam::scope(|scope| {
+            for tld in tlds.iter_mut() {
+                let next = &next;
+                let full = &full;
+                scope.spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    if i >= schedule.len() || stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if !search_tree.playout_from(tld, Some(schedule[i])) {
+                        full.store(true, Ordering::SeqCst);
+                        break;
+                    }
+                });
+            }
+        });
+        !full.load(Ordering::SeqCst)
+    }
     pub fn principal_variation_info(&self, num_moves: usize) -> Vec<MoveInfoHandle<Spec>> {
         self.search_tree.principal_variation(num_moves)
     }
@@ -390,6 +500,7 @@ where
                 * self
                     .principal_variation_info(1)
                     .get(0)
+                    .filter(|x| x.visits() > 0)
                     .map(|x| (x.sum_rewards() / x.visits() as i64) as f32 / SCALE)
                     .unwrap_or(0.0))
             .tan()) as i64
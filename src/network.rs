@@ -0,0 +1,351 @@
+//! A small quantized value network in the style of NNUE.
+//!
+//! The input layer is 768 piece-square features seen from the side to move
+//! (our pieces first, squares mirrored vertically when black is to move).
+//! It feeds an `i16` accumulator, followed by one clipped hidden layer and a
+//! single output. `State` keeps an `Accumulator` for both perspectives and
+//! updates it on each move, so only the layers after it run per evaluation. The output is a logit; its `tanh` is used in the same way
+//! as `Model::score`.
+//!
+//! Weight files are little-endian:
+//!
+//! ```text
+//! magic          4 bytes  "PNET"
+//! version        u32      1
+//! inputs         u32      768
+//! hidden         u32      accumulator size, a multiple of 16
+//! hidden2        u32      second layer size
+//! ft_weights     i16      inputs * hidden, grouped by input
+//! ft_bias        i16      hidden
+//! l1_weights     i16      hidden2 * hidden, grouped by output
+//! l1_bias        i32      hidden2
+//! out_weights    i16      hidden2
+//! out_bias       i32      1
+//! ```
+//!
+//! Feature transformer weights are scaled by `QA`, layer weights by `QB`.
+
+use arc_swap::ArcSwapOption;
+use chess::*;
+use log::debug;
+use once_cell::sync::Lazy;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"PNET";
+const VERSION: u32 = 1;
+pub const NUM_INPUTS: usize = 2 * 6 * 64;
+const QA: i32 = 255;
+const QB: i32 = 64;
+const LANES: usize = 16;
+
+static NETWORK: Lazy<ArcSwapOption<Network>> = Lazy::new(ArcSwapOption::empty);
+
+pub fn set_network_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let network = Network::load(path)?;
+    debug!(
+        "Loaded network {}x{}x{}.",
+        NUM_INPUTS, network.hidden, network.hidden2
+    );
+    NETWORK.store(Some(Arc::new(network)));
+    Ok(())
+}
+
+pub fn loaded_network() -> Option<Arc<Network>> {
+    NETWORK.load_full()
+}
+
+pub struct Network {
+    hidden: usize,
+    hidden2: usize,
+    ft_weights: Vec<i16>,
+    ft_bias: Vec<i16>,
+    l1_weights: Vec<i16>,
+    l1_bias: Vec<i32>,
+    out_weights: Vec<i16>,
+    out_bias: i32,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(invalid("truncated network file"));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+    fn u32(&mut self) -> io::Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    fn i32s(&mut self, n: usize) -> io::Result<Vec<i32>> {
+        let b = self.take(n * 4)?;
+        Ok(b.chunks(4)
+            .map(|x| i32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect())
+    }
+    fn i16s(&mut self, n: usize) -> io::Result<Vec<i16>> {
+        let b = self.take(n * 2)?;
+        Ok(b.chunks(2)
+            .map(|x| i16::from_le_bytes([x[0], x[1]]))
+            .collect())
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Network {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut r = Reader { bytes };
+        if r.take(4)? != MAGIC {
+            return Err(invalid("not a network file"));
+        }
+        if r.u32()? != VERSION {
+            return Err(invalid("unsupported network version"));
+        }
+        if r.u32()? as usize != NUM_INPUTS {
+            return Err(invalid("unexpected number of network inputs"));
+        }
+        let hidden = r.u32()? as usize;
+        let hidden2 = r.u32()? as usize;
+        if hidden == 0 || !hidden.is_multiple_of(LANES) || hidden2 == 0 {
+            return Err(invalid("bad network layer sizes"));
+        }
+        let network = Network {
+            hidden,
+            hidden2,
+            ft_weights: r.i16s(NUM_INPUTS * hidden)?,
+            ft_bias: r.i16s(hidden)?,
+            l1_weights: r.i16s(hidden2 * hidden)?,
+            l1_bias: r.i32s(hidden2)?,
+            out_weights: r.i16s(hidden2)?,
+            out_bias: r.i32s(1)?[0],
+        };
+        if !r.bytes.is_empty() {
+            return Err(invalid("trailing bytes in network file"));
+        }
+        Ok(network)
+    }
+
+    pub fn score(&self, board: &Board) -> f32 {
+        self.output(&self.refresh(board, board.side_to_move()))
+    }
+
+    fn weights(&self, perspective: Color, color: Color, piece: Piece, sq: Square) -> &[i16] {
+        let start = input_index(perspective, color, piece, sq) * self.hidden;
+        &self.ft_weights[start..start + self.hidden]
+    }
+
+    fn refresh(&self, board: &Board, perspective: Color) -> Vec<i16> {
+        let mut acc = self.ft_bias.clone();
+        for sq in *board.combined() {
+            let piece = board.piece_on(sq).unwrap();
+            let color = board.color_on(sq).unwrap();
+            add_assign(&mut acc, self.weights(perspective, color, piece, sq));
+        }
+        acc
+    }
+
+    fn output(&self, acc: &[i16]) -> f32 {
+        let acc: Vec<i16> = acc.iter().map(|&x| clip(x as i32)).collect();
+        let mut out = self.out_bias;
+        for i in 0..self.hidden2 {
+            let w = &self.l1_weights[i * self.hidden..(i + 1) * self.hidden];
+            let x = (dot(&acc, w) + self.l1_bias[i]) / QB;
+            out += clip(x) as i32 * self.out_weights[i] as i32;
+        }
+        (out as f32 / (QA * QB) as f32).tanh()
+    }
+}
+
+fn input_index(stm: Color, color: Color, piece: Piece, sq: Square) -> usize {
+    let sq = if stm == Color::Black {
+        sq.to_index() ^ 56
+    } else {
+        sq.to_index()
+    };
+    let side = if color == stm { 0 } else { 1 };
+    (side * NUM_PIECES + piece.to_index()) * NUM_SQUARES + sq
+}
+
+/// The feature transformer output of a position from both perspectives,
+/// indexed by color.
+#[derive(Clone)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    values: [Vec<i16>; 2],
+}
+
+impl Accumulator {
+    pub fn new(network: Arc<Network>, board: &Board) -> Self {
+        let values = [
+            network.refresh(board, Color::White),
+            network.refresh(board, Color::Black),
+        ];
+        Accumulator { network, values }
+    }
+
+    pub fn network(&self) -> &Arc<Network> {
+        &self.network
+    }
+
+    pub fn update(&mut self, before: &Board, after: &Board) {
+        for &color in &[Color::White, Color::Black] {
+            for &piece in ALL_PIECES.iter() {
+                let was = before.pieces(piece) & before.color_combined(color);
+                let is = after.pieces(piece) & after.color_combined(color);
+                for &perspective in &[Color::White, Color::Black] {
+                    let acc = &mut self.values[perspective.to_index()];
+                    for sq in was & !is {
+                        sub_assign(acc, self.network.weights(perspective, color, piece, sq));
+                    }
+                    for sq in is & !was {
+                        add_assign(acc, self.network.weights(perspective, color, piece, sq));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The network output for `board`, which must be the position this
+    /// accumulator was built or updated for.
+    pub fn score(&self, board: &Board) -> f32 {
+        self.network
+            .output(&self.values[board.side_to_move().to_index()])
+    }
+}
+
+fn clip(x: i32) -> i16 {
+    x.clamp(0, QA) as i16
+}
+
+fn add_assign(acc: &mut [i16], w: &[i16]) {
+    for (a, b) in acc.iter_mut().zip(w) {
+        *a = a.wrapping_add(*b);
+    }
+}
+
+fn sub_assign(acc: &mut [i16], w: &[i16]) {
+    for (a, b) in acc.iter_mut().zip(w) {
+        *a = a.wrapping_sub(*b);
+    }
+}
+
+fn dot(a: &[i16], b: &[i16]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { dot_avx2(a, b) };
+        }
+    }
+    dot_scalar(a, b)
+}
+
+fn dot_scalar(a: &[i16], b: &[i16]) -> i32 {
+    a.iter().zip(b).map(|(x, y)| *x as i32 * *y as i32).sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn dot_avx2(a: &[i16], b: &[i16]) -> i32 {
+    use std::arch::x86_64::*;
+
+    debug_assert!(a.len() == b.len() && a.len().is_multiple_of(LANES));
+    let mut sum = _mm256_setzero_si256();
+    for i in (0..a.len()).step_by(LANES) {
+        let x = _mm256_loadu_si256(a.as_ptr().add(i) as *const __m256i);
+        let y = _mm256_loadu_si256(b.as_ptr().add(i) as *const __m256i);
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(x, y));
+    }
+    let hi = _mm256_extracti128_si256(sum, 1);
+    let s = _mm_add_epi32(_mm256_castsi256_si128(sum), hi);
+    let s = _mm_add_epi32(s, _mm_shuffle_epi32(s, 0b01_00_11_10));
+    let s = _mm_add_epi32(s, _mm_shuffle_epi32(s, 0b10_11_00_01));
+    _mm_cvtsi128_si32(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn to_bytes<F: Fn(usize) -> i16>(hidden: usize, hidden2: usize, ft: F, l1: i16) -> Vec<u8> {
+        let mut v = MAGIC.to_vec();
+        for x in &[VERSION, NUM_INPUTS as u32, hidden as u32, hidden2 as u32] {
+            v.extend_from_slice(&x.to_le_bytes());
+        }
+        for i in 0..NUM_INPUTS * hidden {
+            v.extend_from_slice(&ft(i).to_le_bytes());
+        }
+        for _ in 0..hidden {
+            v.extend_from_slice(&0i16.to_le_bytes());
+        }
+        for _ in 0..hidden2 * hidden {
+            v.extend_from_slice(&l1.to_le_bytes());
+        }
+        for _ in 0..hidden2 {
+            v.extend_from_slice(&0i32.to_le_bytes());
+        }
+        for _ in 0..hidden2 {
+            v.extend_from_slice(&1i16.to_le_bytes());
+        }
+        v.extend_from_slice(&0i32.to_le_bytes());
+        v
+    }
+
+    #[test]
+    fn load_and_score() {
+        let bytes = to_bytes(32, 4, |_| 1, 1);
+        let network = Network::from_bytes(&bytes).unwrap();
+        let board = Board::default();
+        // 32 pieces -> accumulator 32, hidden 32 * 32 / 64 = 16, output 4 * 16
+        let expected = (64. / (QA * QB) as f32).tanh();
+        assert!((network.score(&board) - expected).abs() < 1e-6);
+
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Network::from_bytes(&to_bytes(20, 4, |_| 1, 1)).is_err());
+    }
+
+    #[test]
+    fn perspective() {
+        let bytes = to_bytes(16, 1, |i| (i % 7) as i16 - 2, 1);
+        let network = Network::from_bytes(&bytes).unwrap();
+        let white = Board::from_str("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let black = Board::from_str("4k3/4p3/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(network.score(&white), network.score(&black));
+    }
+
+    #[test]
+    fn incremental() {
+        let bytes = to_bytes(16, 2, |i| (i % 13) as i16 - 6, 1);
+        let network = Arc::new(Network::from_bytes(&bytes).unwrap());
+        let mut board = Board::from_str("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let mut acc = Accumulator::new(network.clone(), &board);
+        for mov in &["e5d6", "e8g8", "b7b8n", "a8b8", "e1c1"] {
+            let after = board.make_move_new(ChessMove::from_str(mov).unwrap());
+            acc.update(&board, &after);
+            board = after;
+            assert_eq!(acc.score(&board), network.score(&board));
+        }
+    }
+
+    #[test]
+    fn simd_dot() {
+        let a: Vec<i16> = (0..64).map(|x| x * 3 - 50).collect();
+        let b: Vec<i16> = (0..64).map(|x| 7 - x).collect();
+        assert_eq!(dot(&a, &b), dot_scalar(&a, &b));
+    }
+}
@@ -1,8 +1,31 @@
-use atomics::{AtomicUsize, Ordering};
-use std::cmp::max;
+use atomics::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+use std::cmp::{max, min};
+use tree_policy::FirstPlayUrgency;
+
+const FPU_REDUCTION: usize = 0;
+const FPU_ABSOLUTE: usize = 1;
+const FPU_LOSS: usize = 2;
 
 static NUM_THREADS: AtomicUsize = AtomicUsize::new(1);
 static HASH_SIZE_MB: AtomicUsize = AtomicUsize::new(16);
+static USE_NETWORK_EVAL: AtomicBool = AtomicBool::new(false);
+static EVAL_CACHE_PERCENT: AtomicUsize = AtomicUsize::new(10);
+static QUIESCENCE_NODES: AtomicUsize = AtomicUsize::new(0);
+static MATE_SEARCH_PLIES: AtomicUsize = AtomicUsize::new(0);
+static FPU_STRATEGY: AtomicUsize = AtomicUsize::new(FPU_ABSOLUTE);
+static FPU_VALUE: AtomicIsize = AtomicIsize::new(0);
+static FPU_STRATEGY_AT_ROOT: AtomicUsize = AtomicUsize::new(FPU_ABSOLUTE);
+static FPU_VALUE_AT_ROOT: AtomicIsize = AtomicIsize::new(0);
+static USE_PUCT: AtomicBool = AtomicBool::new(false);
+static CPUCT_INIT: AtomicUsize = AtomicUsize::new(200);
+static CPUCT_BASE: AtomicUsize = AtomicUsize::new(19652);
+static CPUCT_FACTOR: AtomicUsize = AtomicUsize::new(200);
+static POLICY_TEMPERATURE: AtomicUsize = AtomicUsize::new(100);
+static POLICY_TEMPERATURE_AT_ROOT: AtomicUsize = AtomicUsize::new(100);
+static NOISE_ALPHA: AtomicUsize = AtomicUsize::new(300);
+static NOISE_EPSILON: AtomicUsize = AtomicUsize::new(0);
+static NOISE_SEED: AtomicUsize = AtomicUsize::new(1);
+static GUMBEL_MOVES: AtomicUsize = AtomicUsize::new(0);
 
 pub fn set_num_threads(threads: usize) {
     NUM_THREADS.store(threads, Ordering::Relaxed);
@@ -19,3 +42,170 @@ pub fn set_hash_size_mb(hs: usize) {
 pub fn get_hash_size_mb() -> usize {
     max(1, HASH_SIZE_MB.load(Ordering::Relaxed))
 }
+
+pub fn set_eval_cache_percent(pct: usize) {
+    EVAL_CACHE_PERCENT.store(min(pct, 90), Ordering::Relaxed);
+}
+
+pub fn get_eval_cache_bytes() -> usize {
+    (get_hash_size_mb() << 20) / 100 * EVAL_CACHE_PERCENT.load(Ordering::Relaxed)
+}
+
+/// The part of `Hash` left for the search tree once the eval cache is taken out.
+pub fn get_tree_size_mb() -> usize {
+    max(1, get_hash_size_mb() - (get_eval_cache_bytes() >> 20))
+}
+
+pub fn set_quiescence_nodes(nodes: usize) {
+    QUIESCENCE_NODES.store(nodes, Ordering::Relaxed);
+}
+
+pub fn get_quiescence_nodes() -> usize {
+    QUIESCENCE_NODES.load(Ordering::Relaxed)
+}
+
+pub fn set_mate_search_plies(plies: usize) {
+    MATE_SEARCH_PLIES.store(min(plies, 4), Ordering::Relaxed);
+}
+
+pub fn get_mate_search_plies() -> usize {
+    MATE_SEARCH_PLIES.load(Ordering::Relaxed)
+}
+
+/// Sets the first-play urgency strategy by name, returning false if it is unknown.
+pub fn set_fpu_strategy(name: &str, at_root: bool) -> bool {
+    let strategy = match name {
+        "reduction" => FPU_REDUCTION,
+        "absolute" => FPU_ABSOLUTE,
+        "loss" => FPU_LOSS,
+        _ => return false,
+    };
+    let option = if at_root {
+        &FPU_STRATEGY_AT_ROOT
+    } else {
+        &FPU_STRATEGY
+    };
+    option.store(strategy, Ordering::Relaxed);
+    true
+}
+
+/// Sets the first-play urgency value, in percent of a win.
+pub fn set_fpu_value(percent: isize, at_root: bool) {
+    let option = if at_root {
+        &FPU_VALUE_AT_ROOT
+    } else {
+        &FPU_VALUE
+    };
+    option.store(percent.clamp(-100, 100), Ordering::Relaxed);
+}
+
+/// The first-play urgency, with values as a fraction of a win.
+pub fn get_fpu(at_root: bool) -> FirstPlayUrgency {
+    let (strategy, value) = if at_root {
+        (&FPU_STRATEGY_AT_ROOT, &FPU_VALUE_AT_ROOT)
+    } else {
+        (&FPU_STRATEGY, &FPU_VALUE)
+    };
+    let value = value.load(Ordering::Relaxed) as f32 / 100.;
+    match strategy.load(Ordering::Relaxed) {
+        FPU_REDUCTION => FirstPlayUrgency::Reduction(value),
+        FPU_LOSS => FirstPlayUrgency::Loss,
+        _ => FirstPlayUrgency::Absolute(value),
+    }
+}
+
+pub fn set_use_puct(b: bool) {
+    USE_PUCT.store(b, Ordering::Relaxed);
+}
+
+pub fn get_use_puct() -> bool {
+    USE_PUCT.load(Ordering::Relaxed)
+}
+
+/// Sets the PUCT constants, with `init` and `factor` in hundredths.
+pub fn set_cpuct(init: Option<usize>, base: Option<usize>, factor: Option<usize>) {
+    if let Some(init) = init {
+        CPUCT_INIT.store(init, Ordering::Relaxed);
+    }
+    if let Some(base) = base {
+        CPUCT_BASE.store(max(1, base), Ordering::Relaxed);
+    }
+    if let Some(factor) = factor {
+        CPUCT_FACTOR.store(factor, Ordering::Relaxed);
+    }
+}
+
+/// The PUCT `(init, base, factor)`.
+pub fn get_cpuct() -> (f32, f32, f32) {
+    (
+        CPUCT_INIT.load(Ordering::Relaxed) as f32 / 100.,
+        CPUCT_BASE.load(Ordering::Relaxed) as f32,
+        CPUCT_FACTOR.load(Ordering::Relaxed) as f32 / 100.,
+    )
+}
+
+/// Sets the softmax temperature of the priors, in hundredths.
+pub fn set_policy_temperature(t: usize, at_root: bool) {
+    let option = if at_root {
+        &POLICY_TEMPERATURE_AT_ROOT
+    } else {
+        &POLICY_TEMPERATURE
+    };
+    option.store(max(1, t), Ordering::Relaxed);
+}
+
+pub fn get_policy_temperature(at_root: bool) -> f32 {
+    let option = if at_root {
+        &POLICY_TEMPERATURE_AT_ROOT
+    } else {
+        &POLICY_TEMPERATURE
+    };
+    option.load(Ordering::Relaxed) as f32 / 100.
+}
+
+/// Sets the root noise, with `alpha` in thousandths, from 0.001 to 10, and
+/// `epsilon` in percent.
+pub fn set_dirichlet_noise(alpha: Option<usize>, epsilon: Option<usize>) {
+    if let Some(alpha) = alpha {
+        NOISE_ALPHA.store(alpha.clamp(1, 10_000), Ordering::Relaxed);
+    }
+    if let Some(epsilon) = epsilon {
+        NOISE_EPSILON.store(min(epsilon, 100), Ordering::Relaxed);
+    }
+}
+
+/// The root noise `(alpha, epsilon)`, if it is on.
+pub fn get_dirichlet_noise() -> Option<(f32, f32)> {
+    match NOISE_EPSILON.load(Ordering::Relaxed) {
+        0 => None,
+        epsilon => Some((
+            NOISE_ALPHA.load(Ordering::Relaxed) as f32 / 1000.,
+            epsilon as f32 / 100.,
+        )),
+    }
+}
+
+pub fn set_noise_seed(seed: u32) {
+    NOISE_SEED.store(seed as usize, Ordering::Relaxed);
+}
+
+pub fn get_noise_seed() -> u32 {
+    NOISE_SEED.load(Ordering::Relaxed) as u32
+}
+
+/// Sets how many root moves a Gumbel root search samples; 0 turns it off.
+pub fn set_gumbel_moves(moves: usize) {
+    GUMBEL_MOVES.store(moves, Ordering::Relaxed);
+}
+
+pub fn get_gumbel_moves() -> usize {
+    GUMBEL_MOVES.load(Ordering::Relaxed)
+}
+
+pub fn set_use_network_eval(b: bool) {
+    USE_NETWORK_EVAL.store(b, Ordering::Relaxed);
+}
+
+pub fn get_use_network_eval() -> bool {
+    USE_NETWORK_EVAL.load(Ordering::Relaxed)
+}
@@ -0,0 +1,193 @@
+use atomics::*;
+use chess::*;
+use once_cell::sync::Lazy;
+
+const TABLE_SIZE: usize = 1 << 15;
+const DATA_WORDS: usize = 5;
+
+static PAWN_TABLE: Lazy<PawnHashTable> = Lazy::new(|| PawnHashTable::new(TABLE_SIZE));
+
+/// Pawns of either color with the given property.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PawnStructure {
+    pub passed: BitBoard,
+    pub isolated: BitBoard,
+    pub doubled: BitBoard,
+    pub backward: BitBoard,
+    pub connected: BitBoard,
+}
+
+impl PawnStructure {
+    fn to_words(self) -> [u64; DATA_WORDS] {
+        [
+            self.passed.0,
+            self.isolated.0,
+            self.doubled.0,
+            self.backward.0,
+            self.connected.0,
+        ]
+    }
+    fn from_words(x: &[u64; DATA_WORDS]) -> Self {
+        PawnStructure {
+            passed: BitBoard(x[0]),
+            isolated: BitBoard(x[1]),
+            doubled: BitBoard(x[2]),
+            backward: BitBoard(x[3]),
+            connected: BitBoard(x[4]),
+        }
+    }
+}
+
+/// The pawn structure of the board, looked up in a shared table keyed by the
+/// pawns of both sides.
+pub fn pawn_structure(board: &Board) -> PawnStructure {
+    let pawns = board.pieces(Piece::Pawn);
+    let white = pawns & board.color_combined(Color::White);
+    let black = pawns & board.color_combined(Color::Black);
+    PAWN_TABLE.get(white, black)
+}
+
+fn hash(white: BitBoard, black: BitBoard) -> u64 {
+    let h = white.0.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ black.0.wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h ^ (h >> 29)
+}
+
+/// Squares strictly in front of `sq` from the point of view of `color`.
+fn forward_ranks(color: Color, sq: Square) -> BitBoard {
+    let rank = sq.get_rank().to_index();
+    match color {
+        Color::White if rank == 7 => EMPTY,
+        Color::White => BitBoard(!0u64 << (8 * (rank + 1))),
+        Color::Black => BitBoard((1u64 << (8 * rank)) - 1),
+    }
+}
+
+fn compute(white: BitBoard, black: BitBoard) -> PawnStructure {
+    let mut result = PawnStructure::default();
+    for &(color, ours, theirs) in &[(Color::White, white, black), (Color::Black, black, white)] {
+        for sq in ours {
+            let bb = BitBoard::from_square(sq);
+            let file = get_file(sq.get_file());
+            let adjacent = get_adjacent_files(sq.get_file());
+            let ahead = forward_ranks(color, sq);
+            let behind = !ahead & !get_rank(sq.get_rank());
+            if (theirs & ahead & (file | adjacent)).0 == 0 {
+                result.passed |= bb;
+            }
+            if (ours & adjacent).0 == 0 {
+                result.isolated |= bb;
+            }
+            if (ours & ahead & file).0 != 0 {
+                result.doubled |= bb;
+            }
+            let side_by_side = adjacent & get_rank(sq.get_rank());
+            let supporters = get_pawn_attacks(sq, !color, ours);
+            if ((side_by_side & ours) | supporters).0 != 0 {
+                result.connected |= bb;
+            }
+            let stop_attacked = get_pawn_attacks(stop_square(color, sq), color, theirs).0 != 0;
+            if (ours & adjacent).0 != 0
+                && (ours & adjacent & (behind | get_rank(sq.get_rank()))).0 == 0
+                && stop_attacked
+            {
+                result.backward |= bb;
+            }
+        }
+    }
+    result
+}
+
+fn stop_square(color: Color, sq: Square) -> Square {
+    let next = match color {
+        Color::White => sq.up(),
+        Color::Black => sq.down(),
+    };
+    next.unwrap_or(sq)
+}
+
+struct Entry {
+    key: AtomicU64,
+    pawns: [AtomicU64; 2],
+    data: [AtomicU64; DATA_WORDS],
+}
+
+/// A lockless table; the stored key is the xor of all other words, so a torn
+/// write is treated as a miss.
+struct PawnHashTable {
+    arr: Box<[Entry]>,
+    mask: usize,
+}
+
+impl PawnHashTable {
+    fn new(capacity: usize) -> Self {
+        let arr = (0..capacity)
+            .map(|_| Entry {
+                key: AtomicU64::new(0),
+                pawns: Default::default(),
+                data: Default::default(),
+            })
+            .collect();
+        PawnHashTable {
+            arr,
+            mask: capacity - 1,
+        }
+    }
+
+    fn get(&self, white: BitBoard, black: BitBoard) -> PawnStructure {
+        let entry = &self.arr[hash(white, black) as usize & self.mask];
+        let pawns = [
+            entry.pawns[0].load(Ordering::Relaxed) as u64,
+            entry.pawns[1].load(Ordering::Relaxed) as u64,
+        ];
+        let mut data = [0u64; DATA_WORDS];
+        let mut check = pawns[0] ^ pawns[1];
+        for (x, y) in data.iter_mut().zip(entry.data.iter()) {
+            *x = y.load(Ordering::Relaxed) as u64;
+            check ^= *x;
+        }
+        let key = entry.key.load(Ordering::Relaxed) as u64;
+        // An empty board leaves the entry at all zeroes, which is also its correct value.
+        if key == check && pawns == [white.0, black.0] {
+            return PawnStructure::from_words(&data);
+        }
+
+        let result = compute(white, black);
+        let data = result.to_words();
+        let mut check = white.0 ^ black.0;
+        entry.pawns[0].store(white.0 as FakeU64, Ordering::Relaxed);
+        entry.pawns[1].store(black.0 as FakeU64, Ordering::Relaxed);
+        for (x, y) in data.iter().zip(entry.data.iter()) {
+            y.store(*x as FakeU64, Ordering::Relaxed);
+            check ^= *x;
+        }
+        entry.key.store(check as FakeU64, Ordering::Relaxed);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn squares(names: &[&str]) -> BitBoard {
+        let mut result = EMPTY;
+        for name in names {
+            result |= BitBoard::from_square(Square::from_str(name).unwrap());
+        }
+        result
+    }
+
+    #[test]
+    fn classify() {
+        let board = Board::from_str("4k3/p7/1p6/1P3p2/3P4/2P2P2/2P5/4K3 w - - 0 1").unwrap();
+        let s = pawn_structure(&board);
+        assert_eq!(s.passed, squares(&["d4"]));
+        assert_eq!(s.isolated, squares(&["f3", "f5"]));
+        assert_eq!(s.doubled, squares(&["c2"]));
+        assert_eq!(s.connected, squares(&["d4", "b6"]));
+        assert_eq!(s.backward, squares(&["a7"]));
+        assert_eq!(s, pawn_structure(&board));
+    }
+}
@@ -1,6 +1,7 @@
 use chess::*;
 use features::FeatureVec;
 use features_common::*;
+use see::{see, threat_on};
 use state::{Move, State};
 
 include!(concat!(env!("OUT_DIR"), "/policy_feature_const.rs"));
@@ -170,16 +171,57 @@ where
     if (src_bb & board.pinned()).0 != 0 {
         f(MOVES_PINNED);
     }
+    let exchange = see(board, *mov);
+    f(match exchange {
+        x if x > 0 => SEE_WINNING,
+        0 => SEE_EQUAL,
+        _ => SEE_LOSING,
+    });
+    match exchange {
+        x if x <= -500 => f(SEE_LOSES_BIG),
+        x if x <= -200 => f(SEE_LOSES_MEDIUM),
+        x if x < 0 => f(SEE_LOSES_SMALL),
+        0 => (),
+        x if x < 200 => f(SEE_WINS_SMALL),
+        x if x < 500 => f(SEE_WINS_MEDIUM),
+        _ => f(SEE_WINS_BIG),
+    }
+    if exchange >= 0 && threat_on(board, mov.get_source()) > 0 {
+        f(HANGING_ESCAPE);
+    }
+    let after = board.make_move_new(*mov);
+    let checkers = *after.checkers();
+    if checkers.0 != 0 {
+        f(GIVES_CHECK);
+        let us = board.side_to_move();
+        let moved = after.color_combined(us) & !board.color_combined(us);
+        if (checkers & !moved).0 != 0 {
+            f(GIVES_DISCOVERED_CHECK);
+        }
+        if checkers.popcnt() > 1 {
+            f(GIVES_DOUBLE_CHECK);
+        }
+        if (checkers & dst_bb).0 != 0 {
+            let role = after.piece_on(mov.get_dest()).unwrap();
+            let hits = attacks(mov.get_dest(), role, us, *after.combined())
+                & after.color_combined(!us);
+            for &hit_role in &ROLES[..NUM_ROLES - 1] {
+                if (hits & after.pieces(hit_role)).0 != 0 {
+                    f(FORKS_KING_AND_PAWN + hit_role as usize);
+                }
+            }
+        }
+    }
 }
 
 pub fn featurize(state: &State, mov: &Move) -> FeatureVec {
-    let mut arr = [0u8; NUM_POLICY_FEATURES];
+    let mut arr = [0f32; NUM_POLICY_FEATURES];
     foreach_feature(state, mov, |i, v| {
         assert!(v == 1);
-        arr[i] += v;
+        arr[i] += v as f32;
     });
     for &x in arr.iter() {
-        assert!(x == 0 || x == 1);
+        assert!(x == 0. || x == 1.);
     }
     FeatureVec {
         arr: arr.to_vec(),
@@ -195,6 +237,15 @@ pub fn evaluate_single(state: &State, mov: &Move) -> f32 {
     result
 }
 
+/// The active policy features of a move, each with its coefficient.
+pub fn explain(state: &State, mov: &Move) -> Vec<(String, f32)> {
+    let mut result = Vec::new();
+    foreach_feature(state, mov, |i, _| {
+        result.push((name_feature(i), COEF[i]));
+    });
+    result
+}
+
 pub fn evaluate_moves(state: &State, moves: &[Move]) -> Vec<f32> {
     let mut evalns: Vec<_> = moves.iter().map(|x| evaluate_single(state, x)).collect();
     softmax(&mut evalns);
@@ -284,3 +335,27 @@ fn test_advantage() {
         assert_eq!(adv, 4);
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_checks() {
+    let features = |fen: &str, san: &str| {
+        let s = format!("fen {}", fen);
+        let state = State::from_tokens(s.split_whitespace()).unwrap();
+        let mov = ChessMove::from_san(state.board(), san).unwrap();
+        let mut result = Vec::new();
+        foreach_feature(&state, &mov, |i, _| result.push(i));
+        result
+    };
+    let discovered = features("4k3/8/8/8/8/8/4N3/4R1K1 w - - 0 1", "Nd4");
+    assert!(discovered.contains(&GIVES_CHECK));
+    assert!(discovered.contains(&GIVES_DISCOVERED_CHECK));
+    assert!(!discovered.contains(&GIVES_DOUBLE_CHECK));
+    let double = features("4k3/8/8/8/4N3/8/8/4R1K1 w - - 0 1", "Nf6");
+    assert!(double.contains(&GIVES_DISCOVERED_CHECK));
+    assert!(double.contains(&GIVES_DOUBLE_CHECK));
+    let fork = features("r3k3/8/8/1N6/8/8/8/4K3 w - - 0 1", "Nc7");
+    assert!(fork.contains(&GIVES_CHECK));
+    assert!(fork.contains(&FORKS_KING_AND_ROOK));
+    assert!(!fork.contains(&GIVES_DISCOVERED_CHECK));
+}
@@ -1,28 +1,76 @@
+use atomics::{AtomicUsize, Ordering};
 use chess::{Color, MoveGen, Piece};
-use evaluation::GooseEval;
-use features::Model;
+use evaluation::SearchEval;
+use features;
 use float_ord::FloatOrd;
+use gumbel::gumbel_search;
 use mcts::{AsyncSearchOwned, CycleBehaviour, Evaluator, GameState, MCTSManager, MCTS};
-use options::get_num_threads;
+use options::{
+    get_cpuct, get_dirichlet_noise, get_fpu, get_gumbel_moves, get_noise_seed, get_num_threads,
+    get_policy_temperature, get_use_puct,
+};
+use policy_features;
 use policy_features::evaluate_single;
 use search_tree::PreviousTable;
 use shakmaty_syzygy::Syzygy;
 use state::{Move, Outcome, State, StateBuilder};
+use std::cmp::min;
 use std::sync::mpsc::Sender;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tablebase::probe_tablebase_best_move;
+use time_control::TimeControl;
 use transposition_table::ApproxTable;
-use tree_policy::AlphaGoPolicy;
+use tree_policy::{AlphaGoPolicy, DirichletNoise, PuctPolicy, SearchPolicy};
 use uci::{Tokens, TIMEUP};
 
 const DEFAULT_MOVE_TIME_SECS: u64 = 10;
 const DEFAULT_MOVE_TIME_FRACTION: u32 = 15;
+/// How many times its planned time an unstable search on the clock may run.
+const MAX_TIME_EXTENSION: u32 = 3;
+/// A search on the clock never uses more than 1 / this of the time left.
+const MAX_MOVE_TIME_DIVISOR: u32 = 3;
 
 pub const SCALE: f32 = 1e9;
 
-fn policy() -> AlphaGoPolicy {
-    AlphaGoPolicy::new(2.0 * SCALE)
+/// Counts the seeds handed out, so that each search gets different noise.
+/// The root priors are set before any search thread starts, and the threads'
+/// `PolicyRng`s restart from the same fixed seed in every search, so they
+/// cannot vary the noise between searches. The noise itself is still drawn
+/// from a `PolicyRng`, seeded from this counter and the self-play `--seed`.
+static SEEDS: AtomicUsize = AtomicUsize::new(0);
+
+fn next_seed() -> u32 {
+    let n = SEEDS.fetch_add(1, Ordering::Relaxed) as u32;
+    get_noise_seed() ^ n.wrapping_add(1).wrapping_mul(0x9E37_79B9)
+}
+
+fn root_noise() -> Option<DirichletNoise> {
+    let (alpha, epsilon) = get_dirichlet_noise()?;
+    Some(DirichletNoise::new(alpha, epsilon, next_seed()))
+}
+
+fn policy() -> SearchPolicy {
+    let root_fpu = get_fpu(true).scaled(SCALE);
+    let interior_fpu = get_fpu(false).scaled(SCALE);
+    let root_noise = root_noise();
+    let temperature = (get_policy_temperature(true), get_policy_temperature(false));
+    if get_use_puct() {
+        let (init, base, factor) = get_cpuct();
+        SearchPolicy::Puct(
+            PuctPolicy::new(init * SCALE, base, factor * SCALE)
+                .with_fpu(root_fpu, interior_fpu)
+                .with_temperature(temperature.0, temperature.1)
+                .with_root_noise(root_noise),
+        )
+    } else {
+        SearchPolicy::AlphaGo(
+            AlphaGoPolicy::new(2.0 * SCALE)
+                .with_fpu(root_fpu, interior_fpu)
+                .with_temperature(temperature.0, temperature.1)
+                .with_root_noise(root_noise),
+        )
+    }
 }
 
 pub struct GooseMCTS;
@@ -42,8 +90,8 @@ impl Drop for ThreadSentinel {
 
 impl MCTS for GooseMCTS {
     type State = State;
-    type Eval = GooseEval;
-    type TreePolicy = AlphaGoPolicy;
+    type Eval = SearchEval;
+    type TreePolicy = SearchPolicy;
     type NodeData = ();
     type ExtraThreadData = ThreadSentinel;
     type TranspositionTable = ApproxTable<Self>;
@@ -72,7 +120,7 @@ impl Search {
         MCTSManager::new(
             state.freeze(),
             GooseMCTS,
-            GooseEval::new(Model::new()),
+            SearchEval::new(),
             policy(),
             ApproxTable::enough_to_hold(GooseMCTS.node_limit()),
             prev_table,
@@ -94,15 +142,7 @@ impl Search {
         }
         let manager = self.search.halt();
         if let Some(mov) = manager.best_move() {
-            let info_str = format!(
-                "info nodes {} score cp {} pv{}",
-                manager.tree().num_nodes(),
-                manager.eval_in_cp(),
-                get_pv(&manager)
-            );
-            info!("{}", info_str);
-            println!("{}", info_str);
-            println!("bestmove {}", to_uci(mov));
+            print_result(&manager, mov);
             //manager.tree().display_moves();
         }
         manager
@@ -186,6 +226,7 @@ impl Search {
         }
 
         let mut move_time = None;
+        let mut nodes = None;
         let mut infinite = false;
         let mut remaining = None;
         let mut increment = Duration::from_secs(0);
@@ -193,6 +234,7 @@ impl Search {
         while let Some(s) = tokens.next() {
             match s {
                 "movetime" => move_time = Self::parse_ms(&mut tokens),
+                "nodes" => nodes = tokens.next().and_then(|x| x.parse().ok()),
                 "wtime" => {
                     if player == Color::White {
                         remaining = Self::parse_ms(&mut tokens)
@@ -203,23 +245,57 @@ impl Search {
                         remaining = Self::parse_ms(&mut tokens)
                     }
                 }
+                "winc" => {
+                    if player == Color::White {
+                        increment = Self::parse_ms(&mut tokens).unwrap_or(increment)
+                    }
+                }
+                "binc" => {
+                    if player == Color::Black {
+                        increment = Self::parse_ms(&mut tokens).unwrap_or(increment)
+                    }
+                }
                 "infinite" => infinite = true,
                 _ => (),
             }
         }
 
+        manager.tree().choose_root_move(None);
+
+        if let Some(n) = nodes {
+            let num_threads = get_num_threads();
+            let gumbel_moves = get_gumbel_moves();
+            let seed = next_seed();
+            let sender = sender.clone();
+            let search = manager.into_job_async(move |manager, stop| {
+                match gumbel_moves {
+                    0 => manager.playout_n_parallel_until(n, num_threads, stop),
+                    k => {
+                        gumbel_search(manager, n, k, num_threads, seed, stop);
+                    }
+                }
+                if !stop.load(Ordering::SeqCst) {
+                    let _ = sender.send(format!("{} {}", TIMEUP, position_num));
+                }
+            });
+            return Self { search };
+        }
+
         let mut think_time = Some(Duration::from_secs(DEFAULT_MOVE_TIME_SECS));
+        let mut time_control = None;
 
         if infinite {
             think_time = None
         } else if let Some(mt) = move_time {
             think_time = Some(mt)
         } else if let Some(r) = remaining {
-            let mut t = r / DEFAULT_MOVE_TIME_FRACTION;
+            let mut t = r / DEFAULT_MOVE_TIME_FRACTION + increment * 3 / 4;
 
             t = t - t / mvs.len() as u32;
 
-            think_time = Some(t)
+            let limit = min(t * MAX_TIME_EXTENSION, r / MAX_MOVE_TIME_DIVISOR);
+            think_time = None;
+            time_control = Some(TimeControl::new(t, limit));
         }
 
         if let Some(t) = think_time {
@@ -229,17 +305,34 @@ impl Search {
                 let _ = sender.send(format!("{} {}", TIMEUP, position_num));
             });
         }
-        Self {
-            search: manager.into_playout_parallel_async(get_num_threads()),
-        }
+        let search = match time_control {
+            Some(mut time_control) => {
+                let sender = sender.clone();
+                let start = Instant::now();
+                manager.into_playout_parallel_async_monitored(get_num_threads(), move |tree| {
+                    let moves: Vec<(u64, f32)> = tree
+                        .root_node()
+                        .moves()
+                        .map(|m| (m.visits(), m.average_reward().unwrap_or(0.) / SCALE))
+                        .collect();
+                    if time_control.keep_searching(start.elapsed(), &moves) {
+                        return true;
+                    }
+                    let _ = sender.send(format!("{} {}", TIMEUP, position_num));
+                    false
+                })
+            }
+            None => manager.into_playout_parallel_async(get_num_threads()),
+        };
+        Self { search }
     }
 
-    pub fn print_eval(self) -> Self {
+    pub fn print_eval(self, mut tokens: Tokens) -> Self {
         let manager = self.stop_and_print_m();
 
         let state = manager.tree().root_state();
 
-        let eval = GooseEval::new(Model::new());
+        let eval = SearchEval::new();
 
         let moves = state.available_moves();
         let (move_eval, state_eval) = eval.evaluate_new_state(state, &moves);
@@ -256,6 +349,26 @@ impl Search {
         }
         println!();
 
+        println!("features (win loss draw for the side to move)");
+        for (name, coef) in features::explain(state) {
+            println!(
+                "  {:<48} {:>8.4} {:>8.4} {:>8.4}",
+                name, coef[0], coef[1], coef[2]
+            );
+        }
+
+        if let Some(mov_str) = tokens.next() {
+            match moves.as_slice().iter().find(|m| to_uci(**m) == mov_str) {
+                Some(mov) => {
+                    println!("policy features for {}", mov_str);
+                    for (name, coef) in policy_features::explain(state, mov) {
+                        println!("  {:<48} {:>8.4}", name, coef);
+                    }
+                }
+                None => warn!("{} is not a legal move", mov_str),
+            }
+        }
+
         Self {
             search: manager.into(),
         }
@@ -282,6 +395,18 @@ pub fn to_uci(mov: Move) -> String {
     format!("{}{}{}", mov.get_source(), mov.get_dest(), promo)
 }
 
+fn print_result(manager: &MCTSManager<GooseMCTS>, mov: Move) {
+    let info_str = format!(
+        "info nodes {} score cp {} pv{}",
+        manager.tree().num_nodes(),
+        manager.eval_in_cp(),
+        get_pv(manager)
+    );
+    info!("{}", info_str);
+    println!("{}", info_str);
+    println!("bestmove {}", to_uci(mov));
+}
+
 fn get_pv(m: &MCTSManager<GooseMCTS>) -> String {
     m.principal_variation(10)
         .into_iter()
@@ -2,7 +2,7 @@
 
 use atomics::*;
 use mcts::*;
-use options::get_hash_size_mb;
+use options::get_tree_size_mb;
 use search::GooseMCTS;
 use smallvec::SmallVec;
 use std::fmt;
@@ -16,6 +16,8 @@ use tree_policy::TreePolicy;
 
 use arena::{Arena, ArenaAllocator, ArenaError};
 
+const NO_ROOT_CHOICE: usize = usize::MAX;
+
 /// You're not intended to use this class (use an `MCTSManager` instead),
 /// but you can use it if you want to manage the threads yourself.
 pub struct SearchTree<Spec: MCTS> {
@@ -32,6 +34,8 @@ pub struct SearchTree<Spec: MCTS> {
     transposition_table_hits: AtomicUsize,
     delayed_transposition_table_hits: AtomicUsize,
     expansion_contention_events: AtomicUsize,
+    proven_nodes: AtomicUsize,
+    root_choice: AtomicUsize,
 }
 
 pub struct PreviousTable<Spec: MCTS> {
@@ -284,7 +288,7 @@ fn create_node<'a, 'b, Spec: MCTS>(
     state: &Spec::State,
     ch: CreationHelper<'a, 'b, Spec>,
 ) -> Result<SearchNode<Spec>, ArenaError> {
-    let (allocator, _handle) = match ch {
+    let (allocator, handle) = match ch {
         CreationHelper::Allocator(x) => (x, None),
         CreationHelper::Handle(x) => {
             // this is safe because nothing will move into x.tld.allocator
@@ -294,8 +298,10 @@ fn create_node<'a, 'b, Spec: MCTS>(
         }
     };
     let moves = state.available_moves();
-    let (move_eval, state_eval) = eval.evaluate_new_state(state, &moves);
+    let (mut move_eval, state_eval) = eval.evaluate_new_state(state, &moves);
     policy.validate_evaluations(&move_eval);
+    // Only the root is created without a search handle.
+    policy.adjust_evaluations(&mut move_eval, handle.is_none());
     let hots = allocator.alloc_slice(move_eval.len())?;
     let colds = allocator.alloc_slice(move_eval.len())?;
     for (x, y) in hots.iter_mut().zip(move_eval.into_iter()) {
@@ -320,7 +326,7 @@ impl<Spec: MCTS> SearchTree<Spec> {
         table: Spec::TranspositionTable,
         prev_table: PreviousTable<Spec>,
     ) -> Self {
-        let arena = Box::new(Arena::new(get_hash_size_mb() / 2));
+        let arena = Box::new(Arena::new(get_tree_size_mb() / 2));
         let root_node = create_node(
             &eval,
             &tree_policy,
@@ -341,6 +347,8 @@ impl<Spec: MCTS> SearchTree<Spec> {
             transposition_table_hits: 0.into(),
             delayed_transposition_table_hits: 0.into(),
             expansion_contention_events: 0.into(),
+            proven_nodes: 0.into(),
+            root_choice: NO_ROOT_CHOICE.into(),
         }
     }
 
@@ -363,8 +371,17 @@ impl<Spec: MCTS> SearchTree<Spec> {
         &self.arena
     }
 
-    #[inline(never)]
     pub fn playout<'a: 'b, 'b>(&'a self, tld: &'b mut ThreadData<'a, Spec>) -> bool {
+        self.playout_from(tld, None)
+    }
+
+    /// A playout that starts with the `root_move`th move of the root, if one is given.
+    #[inline(never)]
+    pub fn playout_from<'a: 'b, 'b>(
+        &'a self,
+        tld: &'b mut ThreadData<'a, Spec>,
+        root_move: Option<usize>,
+    ) -> bool {
         const LARGE_DEPTH: usize = 64;
         let sentinel = IncreaseSentinel::new(&self.num_nodes);
         if sentinel.num_nodes >= self.manager.node_limit() {
@@ -389,10 +406,14 @@ impl<Spec: MCTS> SearchTree<Spec> {
             if path.len() >= self.manager.max_playout_length() {
                 break;
             }
-            let choice = match self
-                .manager
-                .override_policy(&playout_data, &state, node.moves())
-            {
+            let forced = match root_move {
+                Some(i) if path.is_empty() => node.moves().nth(i),
+                _ => None,
+            };
+            let choice = match forced.or_else(|| {
+                self.manager
+                    .override_policy(&playout_data, &state, node.moves())
+            }) {
                 Some(choice) => choice,
                 None => self.tree_policy.choose_child(
                     &state,
@@ -495,16 +516,28 @@ impl<Spec: MCTS> SearchTree<Spec> {
             }
         }
 
-        let mut created_here = create_node(
-            &self.eval,
-            &self.tree_policy,
-            state,
-            CreationHelper::Handle(self.make_handle(tld, path)),
-        )?;
+        let prev = self.prev_table.table.lookup(state);
+        // a node the last search expanded was not provable then
+        let proven = match prev {
+            Some(node) if !node.hots().is_empty() => None,
+            _ => self.eval.proven_evaluation(state),
+        };
+        let mut created_here = match proven {
+            Some(evaln) => {
+                self.proven_nodes.fetch_add(1, Ordering::Relaxed);
+                SearchNode::new(&[], &[], evaln)
+            }
+            None => create_node(
+                &self.eval,
+                &self.tree_policy,
+                state,
+                CreationHelper::Handle(self.make_handle(tld, path)),
+            )?,
+        };
 
         let mut did_we_create = true;
 
-        if let Some(node) = self.prev_table.table.lookup(state) {
+        if let Some(node) = prev.filter(|_| proven.is_none()) {
             did_we_create = false;
             let prev_sum = node.sum_evaluations.load(Ordering::Relaxed);
             let prev_visits = node.visits.load(Ordering::Relaxed);
@@ -584,6 +617,18 @@ impl<Spec: MCTS> SearchTree<Spec> {
     pub fn root_state(&self) -> &Spec::State {
         &self.root_state
     }
+    /// Makes the root move at `index` start the principal variation regardless
+    /// of visits, as a Gumbel search does; `None` goes back to picking by visits.
+    pub fn choose_root_move(&self, index: Option<usize>) {
+        self.root_choice
+            .store(index.unwrap_or(NO_ROOT_CHOICE), Ordering::Relaxed);
+    }
+    fn root_choice(&self) -> Option<usize> {
+        match self.root_choice.load(Ordering::Relaxed) {
+            NO_ROOT_CHOICE => None,
+            index => Some(index),
+        }
+    }
     pub fn root_node(&self) -> NodeHandle<Spec> {
         NodeHandle {
             node: &self.root_node,
@@ -594,9 +639,11 @@ impl<Spec: MCTS> SearchTree<Spec> {
         let mut result = Vec::new();
         let mut crnt = &self.root_node;
         while !crnt.hots().is_empty() && result.len() < num_moves {
-            let choice = self
-                .manager
-                .select_child_after_search(&crnt.moves().collect::<Vec<_>>());
+            let moves: Vec<_> = crnt.moves().collect();
+            let choice = match self.root_choice() {
+                Some(i) if result.is_empty() && i < moves.len() => moves[i],
+                _ => self.manager.select_child_after_search(&moves),
+            };
             result.push(choice);
             let child = choice.cold.child.load(Ordering::SeqCst) as *const SearchNode<Spec>;
             if child.is_null() {
@@ -631,6 +678,11 @@ impl<Spec: MCTS> SearchTree<Spec> {
             "{} expansion contention events\n",
             thousands_separate(self.expansion_contention_events.load(Ordering::Relaxed))
         ));
+        s.push_str(&format!(
+            "{} nodes proven by the mate search\n",
+            thousands_separate(self.proven_nodes.load(Ordering::Relaxed))
+        ));
+        s.push_str(&self.eval.diagnose());
         s
     }
 }
@@ -0,0 +1,160 @@
+use chess::*;
+use std::cmp::max;
+
+const MAX_EXCHANGES: usize = 32;
+
+pub fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 100,
+        Piece::Knight => 300,
+        Piece::Bishop => 300,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 20000,
+    }
+}
+
+fn attackers_to(board: &Board, sq: Square, occ: BitBoard) -> BitBoard {
+    let pawns = board.pieces(Piece::Pawn);
+    let bishops = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let rooks = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let result = (get_pawn_attacks(sq, Color::Black, !EMPTY)
+        & pawns
+        & board.color_combined(Color::White))
+        | (get_pawn_attacks(sq, Color::White, !EMPTY) & pawns & board.color_combined(Color::Black))
+        | (get_knight_moves(sq) & board.pieces(Piece::Knight))
+        | (get_king_moves(sq) & board.pieces(Piece::King))
+        | (get_bishop_moves(sq, occ) & bishops)
+        | (get_rook_moves(sq, occ) & rooks);
+    result & occ
+}
+
+fn least_valuable(board: &Board, attackers: BitBoard) -> Option<(Square, Piece)> {
+    for &piece in ALL_PIECES.iter() {
+        let bb = attackers & board.pieces(piece);
+        if bb.0 != 0 {
+            return Some((bb.to_square(), piece));
+        }
+    }
+    None
+}
+
+/// Static exchange evaluation of a move, in centipawns for the side making it.
+///
+/// Both sides recapture on the destination square with their least valuable
+/// attacker and may stop whenever continuing would lose material. Sliders
+/// behind a piece that has captured join in (x-rays); pins are ignored.
+pub fn see(board: &Board, mov: ChessMove) -> i32 {
+    let from = mov.get_source();
+    let to = mov.get_dest();
+    let mover = board.piece_on(from).unwrap();
+    let side = board.color_on(from).unwrap();
+    let mut occ = *board.combined() ^ BitBoard::from_square(from);
+
+    let mut captured = board.piece_on(to).map(piece_value).unwrap_or(0);
+    if mover == Piece::Pawn && from.get_file() != to.get_file() && board.piece_on(to).is_none() {
+        captured = piece_value(Piece::Pawn);
+        occ ^= BitBoard::from_square(Square::make_square(from.get_rank(), to.get_file()));
+    }
+    let mut on_square = mover;
+    if let Some(promotion) = mov.get_promotion() {
+        captured += piece_value(promotion) - piece_value(Piece::Pawn);
+        on_square = promotion;
+    }
+    exchange(board, occ, to, captured, on_square, !side)
+}
+
+/// How much the opponent of the piece on `sq` wins by capturing it, or 0 if the
+/// piece is safe.
+pub fn threat_on(board: &Board, sq: Square) -> i32 {
+    let piece = match board.piece_on(sq) {
+        Some(x) => x,
+        None => return 0,
+    };
+    let enemy = !board.color_on(sq).unwrap();
+    let attackers = attackers_to(board, sq, *board.combined()) & board.color_combined(enemy);
+    match least_valuable(board, attackers) {
+        Some((from, attacker)) => {
+            let occ = *board.combined() ^ BitBoard::from_square(from);
+            max(
+                0,
+                exchange(board, occ, sq, piece_value(piece), attacker, !enemy),
+            )
+        }
+        None => 0,
+    }
+}
+
+/// Plays out the captures on `to` after a first capture worth `captured` that left
+/// `on_square` there. `side` is the side to recapture next.
+fn exchange(
+    board: &Board,
+    mut occ: BitBoard,
+    to: Square,
+    captured: i32,
+    mut on_square: Piece,
+    mut side: Color,
+) -> i32 {
+    let mut gain = [0i32; MAX_EXCHANGES];
+    gain[0] = captured;
+    let mut depth = 0;
+    while depth + 1 < MAX_EXCHANGES {
+        let attackers = attackers_to(board, to, occ);
+        let (from, piece) = match least_valuable(board, attackers & board.color_combined(side)) {
+            Some(x) => x,
+            None => break,
+        };
+        if piece == Piece::King && (attackers & board.color_combined(!side)).0 != 0 {
+            break;
+        }
+        depth += 1;
+        gain[depth] = piece_value(on_square) - gain[depth - 1];
+        occ ^= BitBoard::from_square(from);
+        on_square = piece;
+        side = !side;
+    }
+    while depth > 0 {
+        gain[depth - 1] = -max(-gain[depth - 1], gain[depth]);
+        depth -= 1;
+    }
+    gain[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn see_of(fen: &str, mov: &str) -> i32 {
+        let board = Board::from_str(fen).unwrap();
+        let mov = ChessMove::from_san(&board, mov).unwrap();
+        see(&board, mov)
+    }
+
+    #[test]
+    fn exchanges() {
+        // undefended pawn
+        assert_eq!(see_of("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "Rxe5"), 100);
+        // both sides recapture with sliders x-raying through the first attacker
+        assert_eq!(
+            see_of("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "Nxe5"),
+            -200
+        );
+        // rook takes a defended bishop
+        assert_eq!(see_of("4k3/8/2p5/3b4/8/8/8/3RK3 w - - 0 1", "Rxd5"), -200);
+        // queen takes a pawn defended by a rook
+        assert_eq!(see_of("3rk3/8/8/3p4/8/8/3Q4/3RK3 w - - 0 1", "Qxd5"), -300);
+        // quiet moves
+        assert_eq!(see_of("4k3/8/8/8/8/2p5/8/1N5K w - - 0 1", "Nd2"), -300);
+        assert_eq!(see_of("4k3/8/8/8/8/8/2p5/1N2K3 w - - 0 1", "Na3"), 0);
+    }
+
+    #[test]
+    fn threats() {
+        let board = Board::from_str("4k3/8/2p5/3B4/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(threat_on(&board, Square::D5), 300);
+        let board = Board::from_str("4k3/8/2p5/3B4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(threat_on(&board, Square::D5), 200);
+        assert_eq!(threat_on(&board, Square::E4), 0);
+    }
+}
@@ -0,0 +1,279 @@
+extern crate rand;
+
+use self::rand::{Rng, SeedableRng, XorShiftRng};
+
+use atomics::AtomicBool;
+use chess;
+use features::{featurize, GameResult};
+use gumbel::{gumbel_search, improved_policy};
+use mcts::GameState;
+use options::{get_gumbel_moves, get_num_threads};
+use policy_features;
+use search::{to_uci, Search};
+use search_tree::empty_previous_table;
+use shakmaty;
+use shakmaty::{Position, Setup};
+use state::{Move, Outcome, State};
+use train_data;
+use train_data::{DataFormat, DataKind};
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const MAX_PLIES: usize = 600;
+
+pub struct SelfPlayOptions {
+    pub games: usize,
+    pub nodes: u32,
+    pub random_plies: usize,
+    pub temperature_plies: usize,
+    pub temperature: f32,
+    pub seed: u32,
+    pub format: DataFormat,
+}
+
+struct SearchedPosition {
+    state: State,
+    moves: Vec<Move>,
+    targets: Vec<f32>,
+    played: usize,
+}
+
+/// `policy_<name>` next to the value data at `out_path`.
+fn policy_data_path(out_path: &str) -> PathBuf {
+    let path = Path::new(out_path);
+    let name = path.file_name().map_or("".into(), |x| x.to_string_lossy());
+    path.with_file_name(format!("policy_{}", name))
+}
+
+/// Plays the engine against itself and writes every searched position as value
+/// and policy training data. The policy rows are labelled with the fraction of
+/// root visits their move received, or with the improved policy of a Gumbel
+/// search, which `--fit -p` uses as the target.
+pub fn self_play(options: &SelfPlayOptions, out_path: &str, key_path: &str, pgn_path: &str) {
+    let format = options.format;
+    let mut value_file = train_data::create(out_path, format, DataKind::Value).expect("create");
+    let policy_path = policy_data_path(out_path);
+    let mut policy_file =
+        train_data::create(&policy_path, format, DataKind::Policy).expect("create");
+    let mut key_file = BufWriter::new(File::create(key_path).expect("create"));
+    let mut pgn_file = BufWriter::new(File::create(pgn_path).expect("create"));
+    let mut results = [0; 3];
+    for game in 0..options.games {
+        let mut rng: XorShiftRng = SeedableRng::from_seed([
+            options.seed,
+            game as u32 + 1,
+            0x9E37_79B9,
+            0x7F4A_7C15,
+        ]);
+        let (positions, history, result) = play_game(options, &mut rng);
+        results[result as usize] += 1;
+        for p in &positions {
+            let label = if p.state.board().side_to_move() == chess::Color::White {
+                result
+            } else {
+                result.flip()
+            };
+            featurize(&p.state).write_row(
+                &mut value_file,
+                format,
+                &[label as usize as f32],
+                |_| true,
+            );
+            writeln!(key_file, "{} {}", p.moves.len(), p.played).unwrap();
+            for (mov, &target) in p.moves.iter().zip(p.targets.iter()) {
+                policy_features::featurize(&p.state, mov).write_row(
+                    &mut policy_file,
+                    format,
+                    &[target],
+                    |_| true,
+                );
+            }
+        }
+        write_pgn(&mut pgn_file, game + 1, &history, result);
+        println!(
+            "game {}: {} plies, {} (+{} -{} ={})",
+            game + 1,
+            history.len(),
+            result_str(result),
+            results[GameResult::WhiteWin as usize],
+            results[GameResult::BlackWin as usize],
+            results[GameResult::Draw as usize]
+        );
+    }
+}
+
+fn play_game(
+    options: &SelfPlayOptions,
+    rng: &mut XorShiftRng,
+) -> (Vec<SearchedPosition>, Vec<Move>, GameResult) {
+    let mut state = State::default();
+    let mut history = Vec::new();
+    let mut positions = Vec::new();
+    let mut table = empty_previous_table();
+    while history.len() < MAX_PLIES && !is_over(&state) {
+        let moves = state.available_moves().as_slice().to_vec();
+        let mov = if history.len() < options.random_plies {
+            moves[rng.gen_range(0, moves.len())]
+        } else {
+            let mut manager = Search::create_manager(state.clone(), table);
+            let num_threads = get_num_threads();
+            let stop = AtomicBool::new(false);
+            let gumbel_move = match get_gumbel_moves() {
+                0 => {
+                    manager.playout_n_parallel(options.nodes, num_threads);
+                    None
+                }
+                k => gumbel_search(&manager, options.nodes, k, num_threads, rng.gen(), &stop),
+            };
+            let mut visits = vec![0; moves.len()];
+            let mut root_moves = vec![0; moves.len()];
+            for (j, info) in manager.tree().root_node().moves().enumerate() {
+                if let Some(i) = moves.iter().position(|m| m == info.get_move()) {
+                    visits[i] = info.visits();
+                    root_moves[i] = j;
+                }
+            }
+            let targets = if gumbel_move.is_some() {
+                let policy = improved_policy(&manager);
+                root_moves.iter().map(|&j| policy[j]).collect()
+            } else {
+                let total = visits.iter().sum::<u64>().max(1) as f32;
+                visits.iter().map(|&v| v as f32 / total).collect()
+            };
+            // The Gumbel noise already varies the moves a Gumbel search plays.
+            let played = if let Some(mov) = gumbel_move {
+                moves.iter().position(|m| *m == mov).unwrap()
+            } else if history.len() < options.temperature_plies {
+                sample(&visits, options.temperature, rng)
+            } else {
+                (0..moves.len()).max_by_key(|&i| visits[i]).unwrap()
+            };
+            table = manager.table();
+            positions.push(SearchedPosition {
+                state: state.clone(),
+                moves: moves.clone(),
+                targets,
+                played,
+            });
+            moves[played]
+        };
+        state.make_move(&mov);
+        history.push(mov);
+    }
+    let result = match *state.outcome() {
+        Outcome::WhiteWin => GameResult::WhiteWin,
+        Outcome::BlackWin => GameResult::BlackWin,
+        Outcome::Draw | Outcome::Ongoing => GameResult::Draw,
+    };
+    (positions, history, result)
+}
+
+fn is_over(state: &State) -> bool {
+    let board = state.shakmaty_board();
+    state.outcome() != &Outcome::Ongoing
+        || board.is_insufficient_material()
+        || board.halfmoves() >= 100
+}
+
+/// Picks an index with probability proportional to its visits raised to the
+/// power `1 / temperature`, or the most visited one if `temperature` is 0.
+fn sample(visits: &[u64], temperature: f32, rng: &mut XorShiftRng) -> usize {
+    let most = visits.iter().cloned().max().unwrap_or(0);
+    if most == 0 {
+        return rng.gen_range(0, visits.len());
+    }
+    if temperature <= 0. {
+        return (0..visits.len()).max_by_key(|&i| visits[i]).unwrap();
+    }
+    // Relative to the most visited move, so that low temperatures don't overflow.
+    let exponent = 1. / f64::from(temperature);
+    let weights: Vec<f64> = visits
+        .iter()
+        .map(|&v| (v as f64 / most as f64).powf(exponent))
+        .collect();
+    let mut x = rng.gen::<f64>() * weights.iter().sum::<f64>();
+    for (i, &w) in weights.iter().enumerate() {
+        if x < w {
+            return i;
+        }
+        x -= w;
+    }
+    (0..visits.len()).max_by_key(|&i| visits[i]).unwrap()
+}
+
+fn result_str(result: GameResult) -> &'static str {
+    match result {
+        GameResult::WhiteWin => "1-0",
+        GameResult::BlackWin => "0-1",
+        GameResult::Draw => "1/2-1/2",
+    }
+}
+
+fn write_pgn<W: Write>(f: &mut W, round: usize, history: &[Move], result: GameResult) {
+    writeln!(f, "[Event \"princhess self-play\"]").unwrap();
+    writeln!(f, "[Round \"{}\"]", round).unwrap();
+    writeln!(f, "[White \"princhess\"]").unwrap();
+    writeln!(f, "[Black \"princhess\"]").unwrap();
+    writeln!(f, "[Result \"{}\"]", result_str(result)).unwrap();
+    writeln!(f).unwrap();
+    let mut pos = shakmaty::Chess::default();
+    let mut tokens = Vec::new();
+    for (i, mov) in history.iter().enumerate() {
+        let m = shakmaty::uci::Uci::from_ascii(to_uci(*mov).as_bytes())
+            .unwrap()
+            .to_move(&pos)
+            .unwrap();
+        let san = shakmaty::san::SanPlus::from_move_and_play_unchecked(&mut pos, &m);
+        if i % 2 == 0 {
+            tokens.push(format!("{}. {}", i / 2 + 1, san));
+        } else {
+            tokens.push(san.to_string());
+        }
+    }
+    tokens.push(result_str(result).into());
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + token.len() >= 80 {
+            writeln!(f, "{}", line).unwrap();
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    writeln!(f, "{}\n", line).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_by_temperature() {
+        let visits = [60, 30, 10, 0];
+        let mut rng: XorShiftRng = SeedableRng::from_seed([1, 2, 3, 4]);
+        let counts = |temperature: f32, rng: &mut XorShiftRng| {
+            let mut counts = [0; 4];
+            for _ in 0..10_000 {
+                counts[sample(&visits, temperature, rng)] += 1;
+            }
+            counts
+        };
+
+        let plain = counts(1., &mut rng);
+        assert_eq!(plain[3], 0);
+        assert!((5_700..6_300).contains(&plain[0]), "{:?}", plain);
+        assert!((700..1_300).contains(&plain[2]), "{:?}", plain);
+
+        let cold = counts(0.5, &mut rng);
+        assert!(cold[0] > plain[0] && cold[2] < plain[2], "{:?}", cold);
+        let hot = counts(2., &mut rng);
+        assert!(hot[0] < plain[0] && hot[2] > plain[2], "{:?}", hot);
+
+        assert_eq!(counts(0., &mut rng), [10_000, 0, 0, 0]);
+        assert!(sample(&[0, 0, 0], 1., &mut rng) < 3);
+    }
+}
@@ -1,5 +1,8 @@
 use chess;
+use features::Accumulator;
 use mcts::GameState;
+use network;
+use options::get_use_network_eval;
 use search::to_uci;
 use shakmaty;
 use shakmaty::Position;
@@ -97,8 +100,11 @@ pub struct State {
     repetitions: usize,
     formerly_occupied: [chess::BitBoard; NUM_OCCUPIED_KEPT],
     frozen: bool,
-    queens_off: bool,
-    move_lists: [Vec<chess::ChessMove>; 2],
+    // The positions whose legal moves feed the mobility features, generated
+    // only when a state is evaluated.
+    move_boards: [Option<chess::Board>; 2],
+    accumulator: Accumulator,
+    network_accumulator: Option<network::Accumulator>,
     outcome: Outcome,
 }
 impl State {
@@ -185,15 +191,34 @@ impl State {
             ..self
         }
     }
-    pub fn queens_off(&self) -> bool {
-        self.queens_off
-    }
-    pub fn move_lists(&self) -> &[Vec<chess::ChessMove>; 2] {
-        &self.move_lists
-    }
-
-    pub fn is_endgame(&self) -> bool {
-        self.queens_off()
+    pub fn move_list(&self, index: usize) -> Vec<chess::ChessMove> {
+        self.move_boards[index].map_or_else(Vec::new, |b| chess::MoveGen::new_legal(&b).collect())
+    }
+    pub fn accumulator(&self) -> &Accumulator {
+        &self.accumulator
+    }
+    pub fn network_accumulator(&self) -> Option<&network::Accumulator> {
+        self.network_accumulator.as_ref()
+    }
+
+    /// The board hash mixed with the history the evaluators also read: the
+    /// previous move and capture, the formerly occupied squares and the
+    /// positions behind the mobility move lists.
+    pub fn eval_hash(&self) -> u64 {
+        let prev_move = self.prev_move.map_or(0, |m| {
+            let promotion = m.get_promotion().map_or(0, |p| p.to_index() + 1);
+            1 + m.get_source().to_index() + 64 * m.get_dest().to_index() + 4096 * promotion
+        });
+        let prev_capture = self.prev_capture.map_or(0, |p| p.to_index() + 1);
+        let mut hash = mix_hash(self.board.get_hash(), prev_move as u64);
+        hash = mix_hash(hash, prev_capture as u64);
+        for occupied in &self.formerly_occupied {
+            hash = mix_hash(hash, occupied.0);
+        }
+        for board in &self.move_boards {
+            hash = mix_hash(hash, board.map_or(0, |b| b.get_hash()));
+        }
+        hash
     }
 
     pub fn piece_count(&self) -> u32 {
@@ -201,6 +226,12 @@ impl State {
     }
 }
 
+fn mix_hash(hash: u64, x: u64) -> u64 {
+    (hash ^ x)
+        .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        .rotate_left(31)
+}
+
 impl TranspositionHash for State {
     fn hash(&self) -> u64 {
         match self.repetitions {
@@ -284,8 +315,13 @@ impl From<StateBuilder> for State {
             repetitions: 0,
             formerly_occupied: [*board.combined(); NUM_OCCUPIED_KEPT],
             frozen: false,
-            queens_off: false,
-            move_lists: [Vec::new(), Vec::new()],
+            move_boards: [None, None],
+            accumulator: Accumulator::new(&board),
+            network_accumulator: if get_use_network_eval() {
+                network::loaded_network().map(|n| network::Accumulator::new(n, &board))
+            } else {
+                None
+            },
             outcome: Outcome::Ongoing,
         };
 
@@ -390,12 +426,16 @@ impl GameState for State {
             .to_move(&self.shakmaty_board)
             .unwrap();
         self.shakmaty_board = self.shakmaty_board.clone().play(&shakmaty_move).unwrap();
+        let before = self.board;
         self.board = self.board.make_move_new(*mov);
+        self.accumulator.update(&before, &self.board);
+        if let Some(ref mut acc) = self.network_accumulator {
+            acc.update(&before, &self.board);
+        }
         self.check_for_repetition();
-        self.queens_off = self.queens_off || self.board.pieces(chess::Piece::Queen).0 == 0;
-        self.move_lists.swap(0, 1);
+        self.move_boards.swap(0, 1);
         if self.board.checkers().0 == 0 {
-            self.move_lists[0] = self.available_moves().as_slice().to_vec();
+            self.move_boards[0] = Some(self.board);
         }
         self.check_outcome();
     }
@@ -419,4 +459,23 @@ mod tests {
         let state = State::from(state);
         assert!(state.outcome() == &Outcome::Draw);
     }
+
+    #[test]
+    fn eval_hash_includes_history() {
+        let a = State::from_tokens("startpos moves g1f3 g8f6 f3g1 f6g8".split_whitespace());
+        let b = State::from_tokens("startpos moves b1c3 b8c6 c3b1 c6b8".split_whitespace());
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert_eq!(a.board().get_hash(), b.board().get_hash());
+        assert!(a.eval_hash() != b.eval_hash());
+        assert_eq!(a.eval_hash(), a.clone().eval_hash());
+    }
+
+    #[test]
+    fn incremental_accumulator() {
+        // en passant, an underpromotion with capture and castling on both sides
+        let s = "startpos moves e2e4 d7d5 e4e5 f7f5 e5f6 b8c6 f6g7 c8e6 g7h8n d8d7 g1f3 e8c8 f1e2 a7a6 e1g1";
+        let state = State::from_tokens(s.split_whitespace()).unwrap();
+        let fresh = Accumulator::new(state.board());
+        assert!(fresh.approx_eq(state.accumulator()));
+    }
 }
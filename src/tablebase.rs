@@ -1,19 +1,44 @@
 use arc_swap::ArcSwap;
+use atomics::{AtomicUsize, Ordering};
 use log::debug;
 use once_cell::sync::Lazy;
 use shakmaty::{Chess, Move};
 use shakmaty_syzygy::{Tablebase, Wdl};
+use std::ffi::OsStr;
+use std::fs;
 use std::path::Path;
 use std::sync::Arc;
 
 static TABLEBASE: Lazy<ArcSwap<Tablebase<Chess>>> =
     Lazy::new(|| ArcSwap::from_pointee(Tablebase::new()));
+static CARDINALITY: AtomicUsize = AtomicUsize::new(0);
 
 pub fn set_tablebase_directory<P: AsRef<Path>>(path: P) {
     let mut tb = Tablebase::new();
-    let cnt = tb.add_directory(path).unwrap();
+    let cnt = tb.add_directory(&path).unwrap();
     debug!("Added {} files to tablebase.", cnt);
     TABLEBASE.store(Arc::new(tb));
+    CARDINALITY.store(max_table_pieces(path.as_ref()), Ordering::Relaxed);
+}
+
+/// Pieces in the largest WDL table of a directory, counted from names like `KQvKR.rtbw`.
+fn max_table_pieces(path: &Path) -> usize {
+    fs::read_dir(path)
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension() == Some(OsStr::new("rtbw")))
+        .filter_map(|path| {
+            let stem = path.file_stem()?.to_str()?.to_owned();
+            Some(stem.chars().filter(|&c| c != 'v').count())
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// The most pieces a position may have to be in the loaded tablebase.
+pub fn tablebase_cardinality() -> usize {
+    CARDINALITY.load(Ordering::Relaxed)
 }
 
 pub fn probe_tablebase_wdl(pos: &Chess) -> Option<Wdl> {
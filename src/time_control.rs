@@ -0,0 +1,120 @@
+use std::cmp::{min, Reverse};
+use std::time::Duration;
+
+/// Root visits below which the statistics are too noisy to act on.
+const MIN_VISITS: u64 = 1000;
+/// Fall in the best move's average reward that counts as unstable.
+const Q_DROP: f32 = 0.05;
+
+/// Decides when a search on the clock stops. It is polled with the root move
+/// statistics, stops once the most visited move can no longer be overtaken in
+/// the time left, and pushes the deadline back, up to `limit`, while the best
+/// move changes or its value falls.
+pub struct TimeControl {
+    target: Duration,
+    limit: Duration,
+    deadline: Duration,
+    best: Option<usize>,
+    best_q: f32,
+}
+
+impl TimeControl {
+    pub fn new(target: Duration, limit: Duration) -> Self {
+        let target = min(target, limit);
+        Self {
+            target,
+            limit,
+            deadline: target,
+            best: None,
+            best_q: 0.,
+        }
+    }
+
+    fn extend(&mut self, elapsed: Duration) {
+        let deadline = elapsed + self.target / 2;
+        if deadline > self.deadline {
+            self.deadline = min(deadline, self.limit);
+        }
+    }
+
+    /// `moves` holds the visits and average reward, from -1 to 1, of each root
+    /// move. Returns whether to keep searching.
+    pub fn keep_searching(&mut self, elapsed: Duration, moves: &[(u64, f32)]) -> bool {
+        if elapsed >= self.limit {
+            return false;
+        }
+        let mut by_visits: Vec<usize> = (0..moves.len()).collect();
+        by_visits.sort_by_key(|&i| Reverse(moves[i].0));
+        let best = match by_visits.first() {
+            Some(&i) => i,
+            None => return elapsed < self.deadline,
+        };
+        let (visits, q) = moves[best];
+        let second = by_visits.get(1).map_or(0, |&i| moves[i].0);
+        let total: u64 = moves.iter().map(|m| m.0).sum();
+        let settled = total >= MIN_VISITS;
+
+        if self.best != Some(best) {
+            if settled && self.best.is_some() {
+                self.extend(elapsed);
+            }
+            self.best = Some(best);
+            self.best_q = q;
+        } else if q < self.best_q - Q_DROP {
+            if settled {
+                self.extend(elapsed);
+            }
+            self.best_q = q;
+        } else {
+            self.best_q = self.best_q.max(q);
+        }
+
+        if settled {
+            let rate = total as f32 / elapsed.as_secs_f32();
+            let left = self.deadline.saturating_sub(elapsed).as_secs_f32();
+            if (visits - second) as f32 > rate * left {
+                return false;
+            }
+        }
+        elapsed < self.deadline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(x: u64) -> Duration {
+        Duration::from_millis(x)
+    }
+
+    #[test]
+    fn stops_when_lead_is_out_of_reach() {
+        let mut tc = TimeControl::new(ms(1000), ms(3000));
+        assert!(tc.keep_searching(ms(500), &[(9000, 0.1), (500, 0.)]));
+        assert!(!tc.keep_searching(ms(800), &[(15000, 0.1), (600, 0.)]));
+
+        let mut tc = TimeControl::new(ms(1000), ms(3000));
+        assert!(tc.keep_searching(ms(800), &[(8000, 0.1), (7000, 0.)]));
+        assert!(!tc.keep_searching(ms(1000), &[(10000, 0.1), (9000, 0.)]));
+    }
+
+    #[test]
+    fn extends_when_unstable() {
+        let mut tc = TimeControl::new(ms(1000), ms(3000));
+        assert!(tc.keep_searching(ms(500), &[(5000, 0.1), (4000, 0.)]));
+        assert!(tc.keep_searching(ms(900), &[(6000, 0.), (7000, 0.1)]));
+        assert!(tc.keep_searching(ms(1200), &[(8000, 0.), (8500, 0.1)]));
+        assert!(!tc.keep_searching(ms(1400), &[(9000, 0.), (9500, 0.1)]));
+
+        let mut tc = TimeControl::new(ms(1000), ms(3000));
+        assert!(tc.keep_searching(ms(500), &[(5000, 0.2), (4000, 0.)]));
+        assert!(tc.keep_searching(ms(900), &[(7000, 0.1), (6000, 0.)]));
+        assert!(tc.keep_searching(ms(1200), &[(8000, 0.1), (7500, 0.)]));
+
+        let mut tc = TimeControl::new(ms(1000), ms(1100));
+        assert!(tc.keep_searching(ms(500), &[(5000, 0.1), (4000, 0.)]));
+        assert!(tc.keep_searching(ms(900), &[(6000, 0.), (7000, 0.1)]));
+        assert!(!tc.keep_searching(ms(1100), &[(8000, 0.), (8500, 0.1)]));
+    }
+}
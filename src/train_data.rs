@@ -0,0 +1,366 @@
+//! Training data files, either libsvm text or a packed binary format.
+//!
+//! Binary files start with a header, all integers little-endian:
+//!
+//! ```text
+//! magic            4 bytes  "PTDB"
+//! version          u32      1
+//! kind             u8       0 for value rows, 1 for policy rows
+//! num_features     u32
+//! feature_set_hash u64      hash of the feature list the rows were written with
+//! ```
+//!
+//! followed by rows of LEB128 varints:
+//!
+//! ```text
+//! label_len        varint, then label_len f32 labels
+//! num_entries      varint
+//! entries          varint (delta << 1 | is_one), then an f32 value unless is_one
+//! ```
+//!
+//! Feature indices are zero-based and strictly increasing; each is stored as
+//! the difference to the previous one.
+
+use features::{FEATURE_SET_HASH, NUM_FEATURES};
+use policy_features::{NUM_POLICY_FEATURES, POLICY_FEATURE_SET_HASH};
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+const MAGIC: &[u8; 4] = b"PTDB";
+const VERSION: u32 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    Libsvm,
+    Binary,
+}
+
+impl FromStr for DataFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "libsvm" => Ok(DataFormat::Libsvm),
+            "binary" => Ok(DataFormat::Binary),
+            _ => Err(format!("unknown data format {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DataKind {
+    Value,
+    Policy,
+}
+
+impl DataKind {
+    pub fn num_features(self) -> usize {
+        match self {
+            DataKind::Value => NUM_FEATURES,
+            DataKind::Policy => NUM_POLICY_FEATURES,
+        }
+    }
+    fn feature_set_hash(self) -> u64 {
+        match self {
+            DataKind::Value => FEATURE_SET_HASH,
+            DataKind::Policy => POLICY_FEATURE_SET_HASH,
+        }
+    }
+}
+
+pub struct Row {
+    pub label: Vec<f32>,
+    pub features: Vec<(usize, f32)>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Creates a data file, writing the binary header if needed.
+pub fn create<P: AsRef<Path>>(
+    path: P,
+    format: DataFormat,
+    kind: DataKind,
+) -> io::Result<BufWriter<File>> {
+    let mut f = BufWriter::new(File::create(path)?);
+    if format == DataFormat::Binary {
+        f.write_all(MAGIC)?;
+        f.write_all(&VERSION.to_le_bytes())?;
+        f.write_all(&[kind as u8])?;
+        f.write_all(&(kind.num_features() as u32).to_le_bytes())?;
+        f.write_all(&kind.feature_set_hash().to_le_bytes())?;
+    }
+    Ok(f)
+}
+
+fn write_varint<W: Write>(f: &mut W, mut x: u64) -> io::Result<()> {
+    let mut buf = [0u8; 10];
+    let mut n = 0;
+    loop {
+        let byte = (x & 0x7F) as u8;
+        x >>= 7;
+        if x == 0 {
+            buf[n] = byte;
+            n += 1;
+            break;
+        }
+        buf[n] = byte | 0x80;
+        n += 1;
+    }
+    f.write_all(&buf[..n])
+}
+
+fn read_varint<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut result = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        r.read_exact(&mut byte)?;
+        result |= ((byte[0] & 0x7F) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    Err(invalid("varint too long".into()))
+}
+
+fn read_f32<R: Read>(r: &mut R) -> io::Result<f32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(f32::from_le_bytes(b))
+}
+
+/// Writes one row; `entries` must be sorted by feature index without repeats,
+/// or writing a binary row fails.
+pub fn write_row<W: Write>(
+    f: &mut W,
+    format: DataFormat,
+    label: &[f32],
+    entries: &[(usize, f32)],
+) -> io::Result<()> {
+    match format {
+        DataFormat::Libsvm => {
+            let label: Vec<String> = label.iter().map(|x| x.to_string()).collect();
+            write!(f, "{}", label.join(","))?;
+            for &(index, value) in entries {
+                write!(f, " {}:{}", index + 1, value)?;
+            }
+            writeln!(f)
+        }
+        DataFormat::Binary => {
+            write_varint(f, label.len() as u64)?;
+            for x in label {
+                f.write_all(&x.to_le_bytes())?;
+            }
+            write_varint(f, entries.len() as u64)?;
+            let mut last = 0;
+            for (i, &(index, value)) in entries.iter().enumerate() {
+                if i > 0 && index <= last {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "feature indices must be strictly increasing",
+                    ));
+                }
+                let is_one = value == 1.;
+                write_varint(f, ((index - last) as u64) << 1 | is_one as u64)?;
+                if !is_one {
+                    f.write_all(&value.to_le_bytes())?;
+                }
+                last = index;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn parse_libsvm_row(line: &str, num_features: usize) -> io::Result<Option<Row>> {
+    let mut tokens = line.split_whitespace();
+    let label = match tokens.next() {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let bad = |what: &str| invalid(format!("bad {} in {:?}", what, line));
+    let label = label
+        .split(',')
+        .map(|x| x.parse().map_err(|_| bad("label")))
+        .collect::<io::Result<Vec<f32>>>()?;
+    let mut features = Vec::new();
+    for token in tokens {
+        let mut parts = token.splitn(2, ':');
+        let index: usize = parts
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| bad("feature index"))?;
+        let value: f32 = parts
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| bad("feature value"))?;
+        if index < 1 || index > num_features {
+            return Err(bad("feature index"));
+        }
+        features.push((index - 1, value));
+    }
+    features.sort_by_key(|&(index, _)| index);
+    if features.windows(2).any(|w| w[0].0 == w[1].0) {
+        return Err(bad("duplicate feature index"));
+    }
+    Ok(Some(Row { label, features }))
+}
+
+enum Source {
+    Libsvm(io::Lines<BufReader<File>>),
+    Binary(BufReader<File>),
+}
+
+/// Reads rows from a file in either format, telling them apart by the magic.
+pub struct DataReader {
+    source: Source,
+    num_features: usize,
+}
+
+impl DataReader {
+    pub fn open(path: &str, kind: DataKind) -> io::Result<Self> {
+        let mut r = BufReader::new(File::open(path)?);
+        let is_binary = r.fill_buf()?.starts_with(MAGIC);
+        if !is_binary {
+            return Ok(DataReader {
+                source: Source::Libsvm(r.lines()),
+                num_features: kind.num_features(),
+            });
+        }
+        let mut header = [0u8; 21];
+        r.read_exact(&mut header)?;
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if version != VERSION {
+            return Err(invalid(format!("unsupported data version {}", version)));
+        }
+        if header[8] != kind as u8 {
+            return Err(invalid(format!("{} does not hold {:?} rows", path, kind)));
+        }
+        let mut num_features = [0u8; 4];
+        num_features.copy_from_slice(&header[9..13]);
+        let mut hash = [0u8; 8];
+        hash.copy_from_slice(&header[13..21]);
+        if u32::from_le_bytes(num_features) as usize != kind.num_features()
+            || u64::from_le_bytes(hash) != kind.feature_set_hash()
+        {
+            return Err(invalid(format!(
+                "{} was written with a different feature set",
+                path
+            )));
+        }
+        Ok(DataReader {
+            source: Source::Binary(r),
+            num_features: kind.num_features(),
+        })
+    }
+
+    pub fn is_binary(&self) -> bool {
+        match self.source {
+            Source::Binary(_) => true,
+            Source::Libsvm(_) => false,
+        }
+    }
+
+    fn read_binary_row(r: &mut BufReader<File>, num_features: usize) -> io::Result<Option<Row>> {
+        if r.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let label_len = read_varint(r)? as usize;
+        let label = (0..label_len)
+            .map(|_| read_f32(r))
+            .collect::<io::Result<_>>()?;
+        let num_entries = read_varint(r)? as usize;
+        let mut features = Vec::with_capacity(num_entries);
+        let mut index = 0;
+        for _ in 0..num_entries {
+            let x = read_varint(r)?;
+            index += (x >> 1) as usize;
+            if index >= num_features {
+                return Err(invalid(format!("bad feature index {}", index)));
+            }
+            let value = if x & 1 == 1 { 1. } else { read_f32(r)? };
+            features.push((index, value));
+        }
+        Ok(Some(Row { label, features }))
+    }
+}
+
+impl Iterator for DataReader {
+    type Item = io::Result<Row>;
+
+    fn next(&mut self) -> Option<io::Result<Row>> {
+        let num_features = self.num_features;
+        match self.source {
+            Source::Libsvm(ref mut lines) => loop {
+                let line = match lines.next()? {
+                    Ok(x) => x,
+                    Err(e) => return Some(Err(e)),
+                };
+                match parse_libsvm_row(&line, num_features) {
+                    Ok(Some(row)) => return Some(Ok(row)),
+                    Ok(None) => continue,
+                    Err(e) => return Some(Err(e)),
+                }
+            },
+            Source::Binary(ref mut r) => Self::read_binary_row(r, num_features).transpose(),
+        }
+    }
+}
+
+/// Converts a data file to the other format.
+pub fn convert(in_path: &str, out_path: &str, kind: DataKind) -> io::Result<usize> {
+    let reader = DataReader::open(in_path, kind)?;
+    let format = if reader.is_binary() {
+        DataFormat::Libsvm
+    } else {
+        DataFormat::Binary
+    };
+    let mut out = create(out_path, format, kind)?;
+    let mut rows = 0;
+    for row in reader {
+        let row = row?;
+        write_row(&mut out, format, &row.label, &row.features)?;
+        rows += 1;
+    }
+    out.flush()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn round_trip() {
+        let dir = env::temp_dir();
+        let text = dir.join("princhess_round_trip.libsvm");
+        let binary = dir.join("princhess_round_trip.bin");
+        let back = dir.join("princhess_round_trip_back.libsvm");
+        let (text, binary, back) = (
+            text.to_str().unwrap(),
+            binary.to_str().unwrap(),
+            back.to_str().unwrap(),
+        );
+        let rows = "0 1:1 5:0.5 300:1 30000:2\n0.125 2:1\n2\n0 300:1 5:0.5\n";
+        fs::write(text, rows).unwrap();
+        assert_eq!(convert(text, binary, DataKind::Value).unwrap(), 4);
+        assert!(fs::metadata(binary).unwrap().len() < rows.len() as u64 + 21);
+        assert!(DataReader::open(binary, DataKind::Policy).is_err());
+        assert_eq!(convert(binary, back, DataKind::Value).unwrap(), 4);
+        let sorted = "0 1:1 5:0.5 300:1 30000:2\n0.125 2:1\n2\n0 5:0.5 300:1\n";
+        assert_eq!(fs::read_to_string(back).unwrap(), sorted);
+
+        assert!(parse_libsvm_row("0 5:1 5:0.5", NUM_FEATURES).is_err());
+        let mut sink = Vec::new();
+        let unsorted = [(299, 1.), (4, 0.5)];
+        assert!(write_row(&mut sink, DataFormat::Binary, &[0.], &unsorted).is_err());
+        for path in &[text, binary, back] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,285 @@
+extern crate rand;
+
+use self::rand::{Rng, SeedableRng, XorShiftRng};
+
+use features::{NUM_FEATURES, NUM_OUTCOMES};
+use policy_features::{softmax, NUM_POLICY_FEATURES};
+use train_data::{DataKind, DataReader};
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+pub struct FitOptions {
+    pub epochs: usize,
+    pub learning_rate: f32,
+    pub l2: f32,
+}
+
+/// Sparse rows of a data file, stored back to back.
+struct Rows {
+    offsets: Vec<usize>,
+    indices: Vec<u32>,
+    values: Vec<f32>,
+    labels: Vec<Vec<f32>>,
+}
+
+impl Rows {
+    fn read(path: &str, kind: DataKind) -> Self {
+        let mut rows = Rows {
+            offsets: vec![0],
+            indices: Vec::new(),
+            values: Vec::new(),
+            labels: Vec::new(),
+        };
+        for row in DataReader::open(path, kind).expect("fopen") {
+            let row = row.expect("read");
+            rows.labels.push(row.label);
+            for (index, value) in row.features {
+                rows.indices.push(index as u32);
+                rows.values.push(value);
+            }
+            rows.offsets.push(rows.indices.len());
+        }
+        rows
+    }
+
+    fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    fn row(&self, i: usize) -> impl Iterator<Item = (usize, f32)> + '_ {
+        let range = self.offsets[i]..self.offsets[i + 1];
+        self.indices[range.clone()]
+            .iter()
+            .map(|&x| x as usize)
+            .zip(self.values[range].iter().cloned())
+    }
+}
+
+/// AdaGrad with L2 regularization on a dense weight matrix.
+struct Weights {
+    w: Vec<f32>,
+    g2: Vec<f32>,
+    width: usize,
+}
+
+impl Weights {
+    fn new(num_features: usize, width: usize) -> Self {
+        Weights {
+            w: vec![0.; num_features * width],
+            g2: vec![0.; num_features * width],
+            width,
+        }
+    }
+
+    fn dot(&self, row: impl Iterator<Item = (usize, f32)>, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = 0.;
+        }
+        for (i, v) in row {
+            for (k, x) in out.iter_mut().enumerate() {
+                *x += self.w[i * self.width + k] * v;
+            }
+        }
+    }
+
+    fn step(&mut self, row: impl Iterator<Item = (usize, f32)>, grad: &[f32], options: &FitOptions) {
+        for (i, v) in row {
+            for (k, g) in grad.iter().enumerate() {
+                let j = i * self.width + k;
+                let g = g * v + options.l2 * self.w[j];
+                self.g2[j] += g * g;
+                self.w[j] -= options.learning_rate * g / (self.g2[j].sqrt() + 1e-8);
+            }
+        }
+    }
+}
+
+fn shuffled(n: usize, rng: &mut XorShiftRng) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..n).collect();
+    rng.shuffle(&mut order);
+    order
+}
+
+/// The target distribution of a value label: an outcome or outcome
+/// probabilities, optionally followed by the tablebase outcome (-1 if none),
+/// which is weighted equally.
+fn value_target(label: &[f32]) -> [f32; NUM_OUTCOMES] {
+    let (target, tablebase) = match label.len() {
+        1 | NUM_OUTCOMES => (label, None),
+        2 | 4 => (&label[..label.len() - 1], Some(label[label.len() - 1])),
+        _ => panic!("bad value label {:?}", label),
+    };
+    let mut result = [0.; NUM_OUTCOMES];
+    if target.len() == 1 {
+        result[target[0] as usize] = 1.;
+    } else {
+        result.copy_from_slice(target);
+    }
+    if let Some(tablebase) = tablebase.filter(|&x| x >= 0.) {
+        for x in result.iter_mut() {
+            *x *= 0.5;
+        }
+        result[tablebase as usize] += 0.5;
+    }
+    result
+}
+
+/// Fits the value model by multinomial logistic regression over the game outcome.
+pub fn fit_value(data_path: &str, model_path: &str, options: &FitOptions) {
+    let rows = Rows::read(data_path, DataKind::Value);
+    let targets: Vec<_> = rows.labels.iter().map(|x| value_target(x)).collect();
+    println!("{} value rows", rows.len());
+    let mut weights = Weights::new(NUM_FEATURES, NUM_OUTCOMES);
+    let mut rng: XorShiftRng = SeedableRng::from_seed([1, 2, 3, 4]);
+    let mut probs = [0f32; NUM_OUTCOMES];
+    for epoch in 0..options.epochs {
+        let mut loss = 0.;
+        for i in shuffled(rows.len(), &mut rng) {
+            weights.dot(rows.row(i), &mut probs);
+            softmax(&mut probs);
+            let mut grad = [0f32; NUM_OUTCOMES];
+            for k in 0..NUM_OUTCOMES {
+                grad[k] = probs[k] - targets[i][k];
+                loss -= targets[i][k] * probs[k].max(1e-12).ln();
+            }
+            weights.step(rows.row(i), &grad, options);
+        }
+        println!(
+            "epoch {}: loss {:.6}",
+            epoch + 1,
+            loss / rows.len().max(1) as f32
+        );
+    }
+    write_value_model(model_path, &weights.w);
+}
+
+/// Positions of the policy data: the rows of a position are its legal moves, and
+/// `policy_key.txt` lists the number of moves and the index of the one played.
+/// Nonzero row labels are used as the target distribution instead.
+fn read_policy_groups(key_path: &str, rows: &Rows) -> Vec<(usize, usize, Vec<f32>)> {
+    let file = BufReader::new(File::open(key_path).expect("fopen"));
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for line in file.lines() {
+        let line = line.expect("read");
+        let mut tokens = line.split_whitespace();
+        let n: usize = tokens.next().expect("move count").parse().expect("move count");
+        let played: usize = tokens.next().expect("move index").parse().expect("move index");
+        assert!(start + n <= rows.len(), "key file does not match the data");
+        let mut target: Vec<f32> = rows.labels[start..start + n]
+            .iter()
+            .map(|x| x[0])
+            .collect();
+        let total: f32 = target.iter().sum();
+        if total > 0. {
+            for x in target.iter_mut() {
+                *x /= total;
+            }
+        } else {
+            target[played] = 1.;
+        }
+        groups.push((start, n, target));
+        start += n;
+    }
+    assert_eq!(start, rows.len(), "key file does not match the data");
+    groups
+}
+
+/// Fits the policy model by a softmax over the legal moves of each position.
+pub fn fit_policy(data_path: &str, key_path: &str, model_path: &str, options: &FitOptions) {
+    let rows = Rows::read(data_path, DataKind::Policy);
+    let groups = read_policy_groups(key_path, &rows);
+    println!("{} policy positions", groups.len());
+    let mut weights = Weights::new(NUM_POLICY_FEATURES, 1);
+    let mut rng: XorShiftRng = SeedableRng::from_seed([1, 2, 3, 4]);
+    let mut logit = [0f32];
+    for epoch in 0..options.epochs {
+        let mut loss = 0.;
+        for g in shuffled(groups.len(), &mut rng) {
+            let (start, n, ref target) = groups[g];
+            let mut probs: Vec<f32> = (start..start + n)
+                .map(|i| {
+                    weights.dot(rows.row(i), &mut logit);
+                    logit[0]
+                })
+                .collect();
+            softmax(&mut probs);
+            for (j, i) in (start..start + n).enumerate() {
+                loss -= target[j] * probs[j].max(1e-12).ln();
+                weights.step(rows.row(i), &[probs[j] - target[j]], options);
+            }
+        }
+        println!(
+            "epoch {}: loss {:.6}",
+            epoch + 1,
+            loss / groups.len().max(1) as f32
+        );
+    }
+    write_policy_model(model_path, &weights.w);
+}
+
+/// Formats like numpy's `array2string`, which is what build.rs was written for.
+fn format_coef(x: f32, digits: usize) -> String {
+    let s = format!("{:.*e}", digits, x);
+    let (mantissa, exponent) = s.split_at(s.find('e').unwrap());
+    let exponent: i32 = exponent[1..].parse().unwrap();
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+fn write_value_model(path: &str, w: &[f32]) {
+    let mut f = BufWriter::new(File::create(path).expect("create"));
+    let rows: Vec<String> = w
+        .chunks(NUM_OUTCOMES)
+        .map(|row| {
+            let row: Vec<String> = row
+                .iter()
+                .map(|&x| {
+                    let s = format_coef(x, 8);
+                    if x < 0. {
+                        s
+                    } else {
+                        format!(" {}", s)
+                    }
+                })
+                .collect();
+            format!("[{}]", row.join(","))
+        })
+        .collect();
+    writeln!(f, "[{}]", rows.join(",\n ")).unwrap();
+}
+
+fn write_policy_model(path: &str, w: &[f32]) {
+    let mut f = BufWriter::new(File::create(path).expect("create"));
+    write!(f, "[").unwrap();
+    for (i, &x) in w.iter().enumerate() {
+        write!(f, "{},", format_coef(x, 10)).unwrap();
+        if i + 1 < w.len() {
+            writeln!(f).unwrap();
+        }
+    }
+    writeln!(f, "]").unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numpy_format() {
+        assert_eq!(format_coef(-1.5, 8), "-1.50000000e+00");
+        assert_eq!(format_coef(0.03125, 8), "3.12500000e-02");
+        assert_eq!(format_coef(0., 10), "0.0000000000e+00");
+    }
+
+    #[test]
+    fn value_targets() {
+        assert_eq!(value_target(&[2.]), [0., 0., 1.]);
+        assert_eq!(value_target(&[0., -1.]), [1., 0., 0.]);
+        assert_eq!(value_target(&[0., 2.]), [0.5, 0., 0.5]);
+        assert_eq!(value_target(&[1., 1.]), [0., 1., 0.]);
+        assert_eq!(value_target(&[0.5, 0.25, 0.25]), [0.5, 0.25, 0.25]);
+        assert_eq!(value_target(&[0.5, 0.25, 0.25, 2.]), [0.25, 0.125, 0.625]);
+    }
+}
@@ -1,104 +1,518 @@
+extern crate crossbeam;
 extern crate memmap;
 extern crate pgn_reader;
 extern crate rand;
+extern crate shakmaty_syzygy;
 
 use self::memmap::Mmap;
-use self::pgn_reader::{BufferedReader, Outcome, RawHeader, SanPlus, Skip, Visitor};
+use self::pgn_reader::{BufferedReader, Outcome, RawComment, RawHeader, SanPlus, Skip, Visitor};
 use self::rand::{Rng, SeedableRng, XorShiftRng};
+use self::shakmaty_syzygy::Wdl;
 
+use atomics::{AtomicUsize, Ordering};
 use chess;
-use features::{featurize, name_feature, GameResult, NUM_DENSE_FEATURES, NUM_FEATURES};
+use features::{
+    featurize, name_feature, GameResult, NUM_DENSE_FEATURES, NUM_FEATURES, NUM_OUTCOMES,
+};
 use mcts::GameState;
 use policy_features;
 use policy_features::NUM_POLICY_FEATURES;
+use see::see;
 use shakmaty;
-use state::StateBuilder;
+use state::{State, StateBuilder};
+use tablebase::{probe_tablebase_wdl, tablebase_cardinality};
+use train_data;
+use train_data::{DataFormat, DataKind, DataReader};
 
 use std;
-use std::fs::File;
+use std::cmp::min;
+use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 use std::str;
+use std::str::FromStr;
+use std::sync::Mutex;
 
 const NUM_ROWS: usize = std::usize::MAX;
-const MIN_ELO: i32 = 1700;
-const MIN_ELO_POLICY: i32 = 2200;
-const NUM_SAMPLES: usize = 4;
+const CHUNK_BYTES: usize = 1 << 26;
 
-struct ValueDataGenerator {
-    out_file: Option<BufWriter<File>>,
+/// What to do with the tablebase result of a value sample.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TbLabels {
+    /// Use it instead of the game result.
+    Replace,
+    /// Write it as a second label after the game result, or -1 if the position
+    /// is not in the tablebase.
+    Extra,
+}
+
+impl FromStr for TbLabels {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "replace" => Ok(TbLabels::Replace),
+            "extra" => Ok(TbLabels::Extra),
+            _ => Err(format!("unknown tablebase label mode {}", s)),
+        }
+    }
+}
+
+pub struct TrainingConfig {
+    pub format: DataFormat,
+    pub threads: usize,
+    pub min_elo: i32,
+    pub policy_min_elo: i32,
+    /// Average number of positions sampled per game for value data.
+    pub samples: usize,
+    /// Opening plies never sampled for value data.
+    pub skip_plies: usize,
+    /// Value features seen in fewer positions are left out of the output.
+    pub min_frequency: u64,
+    /// Drop value samples that are not quiet, see `noise`.
+    pub quiet_only: bool,
+    /// Label value samples within the loaded tablebase from it.
+    pub tb_labels: Option<TbLabels>,
+    /// Weight of the `[%eval]` comment in the value target, or 0 to use only
+    /// the result. Nonzero weights write "win,loss,draw" probability labels.
+    pub eval_weight: f32,
+    pub seed: u32,
+    /// Accepted `TimeControl` headers, or empty to accept all.
+    pub time_controls: Vec<String>,
+    /// Accepted `Termination` headers, or empty to accept all.
+    pub terminations: Vec<String>,
+    /// Accepted `Variant` headers; games without one are "Standard".
+    pub variants: Vec<String>,
+}
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        TrainingConfig {
+            format: DataFormat::Libsvm,
+            threads: 1,
+            min_elo: 1700,
+            policy_min_elo: 2200,
+            samples: 4,
+            skip_plies: 2,
+            min_frequency: 500,
+            quiet_only: false,
+            tb_labels: None,
+            eval_weight: 0.,
+            seed: 1,
+            time_controls: Vec::new(),
+            terminations: Vec::new(),
+            variants: vec!["Standard".into()],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SkipReason {
+    LowElo,
+    BadElo,
+    Variant,
+    TimeControl,
+    Termination,
+    IllegalSan,
+    MissingResult,
+}
+
+const NUM_SKIP_REASONS: usize = 7;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Noise {
+    InCheck,
+    PendingRecapture,
+    WinningCapture,
+}
+
+const NUM_NOISE_KINDS: usize = 3;
+
+/// Why the side to move is not in a quiet position, if it is not: it is in
+/// check, can take back the piece that just captured, or has a capture that
+/// wins material by static exchange.
+fn noise(state: &State) -> Option<Noise> {
+    let board = state.board();
+    if board.checkers().popcnt() > 0 {
+        return Some(Noise::InCheck);
+    }
+    let moves = state.available_moves();
+    let moves = moves.as_slice();
+    if let (Some(_), Some(prev)) = (state.prev_capture(), state.prev_move()) {
+        if moves.iter().any(|m| m.get_dest() == prev.get_dest()) {
+            return Some(Noise::PendingRecapture);
+        }
+    }
+    let is_capture = |m: &chess::ChessMove| {
+        board.piece_on(m.get_dest()).is_some()
+            || (board.piece_on(m.get_source()) == Some(chess::Piece::Pawn)
+                && m.get_source().get_file() != m.get_dest().get_file())
+    };
+    if moves.iter().any(|&m| is_capture(&m) && see(board, m) > 0) {
+        return Some(Noise::WinningCapture);
+    }
+    None
+}
+
+/// The result of the position for the side to move according to the tablebase.
+fn tablebase_result(state: &State) -> Option<GameResult> {
+    if state.piece_count() as usize > tablebase_cardinality() {
+        return None;
+    }
+    match probe_tablebase_wdl(state.shakmaty_board())? {
+        Wdl::Win => Some(GameResult::WhiteWin),
+        Wdl::Loss => Some(GameResult::BlackWin),
+        _ => Some(GameResult::Draw),
+    }
+}
+
+/// An engine evaluation from a `[%eval]` comment, for white.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Eval {
+    Centipawns(f32),
+    /// Mate in the given number of moves, negative if black mates.
+    Mate(i32),
+}
+
+const EVAL_DRAW_MARGIN: f32 = 100.;
+const EVAL_SCALE: f32 = 100.;
+
+fn parse_eval(comment: &[u8]) -> Option<Eval> {
+    let comment = str::from_utf8(comment).ok()?;
+    let start = comment.find("[%eval ")? + "[%eval ".len();
+    let value = comment[start..].split(|c: char| c == ']' || c.is_whitespace()).next()?;
+    match value.strip_prefix('#') {
+        Some(mate) => mate.parse().ok().map(Eval::Mate),
+        None => value.parse::<f32>().ok().map(|x| Eval::Centipawns(x * 100.)),
+    }
+}
+
+/// Outcome probabilities for the side to move, in `GameResult` order. Each
+/// side wins when it is more than `EVAL_DRAW_MARGIN` ahead under logistic noise.
+fn eval_probabilities(eval: Eval, white_to_move: bool) -> [f32; NUM_OUTCOMES] {
+    let sign = if white_to_move { 1. } else { -1. };
+    let cp = match eval {
+        Eval::Mate(n) if (n as f32) * sign > 0. => return [1., 0., 0.],
+        Eval::Mate(n) if (n as f32) * sign < 0. => return [0., 1., 0.],
+        Eval::Mate(_) => return [0., 0., 1.],
+        Eval::Centipawns(x) => x * sign,
+    };
+    let sigmoid = |x: f32| 1. / (1. + (-x).exp());
+    let win = sigmoid((cp - EVAL_DRAW_MARGIN) / EVAL_SCALE);
+    let loss = sigmoid((-cp - EVAL_DRAW_MARGIN) / EVAL_SCALE);
+    [win, loss, 1. - win - loss]
+}
+
+/// Counts of the games read and why the unused ones were skipped, of the
+/// value samples dropped by `--quiet-only`, and of the samples labelled from
+/// the tablebase or an eval comment.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct SkipStats {
+    games: usize,
+    skipped: [usize; NUM_SKIP_REASONS],
+    noisy: [usize; NUM_NOISE_KINDS],
+    tb_labels: usize,
+    tb_disagreements: usize,
+    eval_labels: usize,
+}
+
+impl SkipStats {
+    fn add(&mut self, other: &SkipStats) {
+        self.games += other.games;
+        for (x, y) in self.skipped.iter_mut().zip(other.skipped.iter()) {
+            *x += y;
+        }
+        for (x, y) in self.noisy.iter_mut().zip(other.noisy.iter()) {
+            *x += y;
+        }
+        self.tb_labels += other.tb_labels;
+        self.tb_disagreements += other.tb_disagreements;
+        self.eval_labels += other.eval_labels;
+    }
+
+    fn print(&self) {
+        let skipped: usize = self.skipped.iter().sum();
+        println!("{} games, {} used", self.games, self.games - skipped);
+        let names = [
+            "below minimum elo",
+            "bad elo header",
+            "variant or custom start",
+            "time control",
+            "termination",
+            "illegal move",
+            "missing result",
+        ];
+        for (name, &count) in names.iter().zip(self.skipped.iter()) {
+            if count > 0 {
+                println!("  skipped {}: {}", name, count);
+            }
+        }
+        let names = ["in check", "pending recapture", "winning capture"];
+        for (name, &count) in names.iter().zip(self.noisy.iter()) {
+            if count > 0 {
+                println!("  samples dropped, {}: {}", name, count);
+            }
+        }
+        if self.tb_labels > 0 {
+            println!(
+                "{} samples in the tablebase, {} disagree with the game result",
+                self.tb_labels, self.tb_disagreements
+            );
+        }
+        if self.eval_labels > 0 {
+            println!("{} samples blended with an eval", self.eval_labels);
+        }
+    }
+}
+
+fn accepts(list: &[String], value: &str) -> bool {
+    list.is_empty() || list.iter().any(|x| x.eq_ignore_ascii_case(value))
+}
+
+const FILTERED_HEADERS: [&[u8]; 3] = [b"TimeControl", b"Termination", b"Variant"];
+
+/// Follows the headers and mainline of a game and decides whether it is usable.
+struct GameReader<'a> {
+    config: &'a TrainingConfig,
+    min_elo: i32,
     state: StateBuilder,
-    skip: bool,
-    rows_written: usize,
-    rng: XorShiftRng,
-    freq: [u64; NUM_FEATURES],
-    whitelist: [bool; NUM_FEATURES],
+    headers: [Option<String>; 3],
+    /// The eval after each move, if the game has one.
+    evals: Vec<Option<Eval>>,
+    result: Option<GameResult>,
+    reason: Option<SkipReason>,
+    stats: SkipStats,
 }
 
-impl Visitor for ValueDataGenerator {
-    type Result = ();
+impl<'a> GameReader<'a> {
+    fn new(config: &'a TrainingConfig, min_elo: i32) -> Self {
+        GameReader {
+            config,
+            min_elo,
+            state: StateBuilder::default(),
+            headers: Default::default(),
+            evals: Vec::new(),
+            result: None,
+            reason: None,
+            stats: SkipStats::default(),
+        }
+    }
 
     fn begin_game(&mut self) {
         self.state = StateBuilder::default();
-        self.skip = self.rows_written == NUM_ROWS;
+        self.headers = Default::default();
+        self.evals.clear();
+        self.result = None;
+        self.reason = None;
     }
 
-    fn san(&mut self, san: SanPlus) {
-        if let Ok(m) = san.san.to_move(self.state.chess()) {
-            self.state.make_move(m);
+    /// Applies the header filters; returns whether to skip the movetext.
+    fn end_headers(&mut self) -> bool {
+        let config = self.config;
+        let header = |i: usize, default: &'static str| -> String {
+            self.headers[i].clone().unwrap_or_else(|| default.into())
+        };
+        let reason = if !accepts(&config.time_controls, &header(0, "")) {
+            Some(SkipReason::TimeControl)
+        } else if !accepts(&config.terminations, &header(1, "")) {
+            Some(SkipReason::Termination)
+        } else if !accepts(&config.variants, &header(2, "Standard")) {
+            Some(SkipReason::Variant)
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            self.skip(reason);
         }
+        self.reason.is_some()
     }
 
-    fn end_headers(&mut self) -> Skip {
-        Skip(self.skip)
+    fn skip(&mut self, reason: SkipReason) {
+        if self.reason.is_none() {
+            self.reason = Some(reason);
+        }
     }
 
     fn header(&mut self, key: &[u8], value: RawHeader) {
         if key == b"WhiteElo" || key == b"BlackElo" {
-            let elo: i32 = value.decode_utf8().unwrap().parse().unwrap();
-            if elo < MIN_ELO {
-                self.skip = true;
+            match value.decode_utf8().ok().and_then(|x| x.parse::<i32>().ok()) {
+                Some(elo) if elo < self.min_elo => self.skip(SkipReason::LowElo),
+                Some(_) => {}
+                None => self.skip(SkipReason::BadElo),
+            }
+        } else if let Some(i) = FILTERED_HEADERS.iter().position(|&x| x == key) {
+            self.headers[i] = Some(value.decode_utf8_lossy().into_owned());
+        } else if key == b"FEN" || key == b"SetUp" {
+            self.skip(SkipReason::Variant);
+        }
+    }
+
+    fn san(&mut self, san: SanPlus) {
+        if self.reason.is_some() {
+            return;
+        }
+        match san.san.to_move(self.state.chess()) {
+            Ok(m) => {
+                self.state.make_move(m);
+                self.evals.push(None);
+            }
+            Err(_) => self.skip(SkipReason::IllegalSan),
+        }
+    }
+
+    fn comment(&mut self, comment: RawComment) {
+        if self.config.eval_weight > 0. {
+            if let Some(eval) = parse_eval(comment.as_bytes()) {
+                if let Some(last) = self.evals.last_mut() {
+                    *last = Some(eval);
+                }
             }
         }
     }
 
     fn outcome(&mut self, outcome: Option<Outcome>) {
-        let game_result = match outcome {
-            Some(Outcome::Draw) => GameResult::Draw,
+        self.result = match outcome {
+            Some(Outcome::Draw) => Some(GameResult::Draw),
             Some(Outcome::Decisive { winner }) => {
                 if winner == shakmaty::Color::White {
-                    GameResult::WhiteWin
+                    Some(GameResult::WhiteWin)
                 } else {
-                    GameResult::BlackWin
+                    Some(GameResult::BlackWin)
                 }
             }
-            None => return,
+            None => None,
         };
-        let (mut state, moves) = self.state.extract();
-        let freq = NUM_SAMPLES as f64 / moves.len() as f64;
-        for (i, m) in moves.into_iter().enumerate() {
-            if i >= 2 && self.rng.gen_range(0., 1.) < freq {
-                let mut f = featurize(&state);
-                self.rows_written += 1;
-                if let Some(out_file) = self.out_file.as_mut() {
-                    let whitelist = &self.whitelist;
-                    let crnt_result = if state.board().side_to_move() == chess::Color::White {
-                        game_result
-                    } else {
-                        game_result.flip()
-                    };
-                    f.write_libsvm(out_file, crnt_result as usize, |x| whitelist[x]);
+    }
+
+    /// Counts the game and returns its result if it should be used.
+    fn end_game(&mut self) -> Option<GameResult> {
+        if self.reason.is_none() && self.result.is_none() {
+            self.reason = Some(SkipReason::MissingResult);
+        }
+        self.stats.games += 1;
+        match self.reason {
+            Some(reason) => {
+                self.stats.skipped[reason as usize] += 1;
+                None
+            }
+            None => self.result,
+        }
+    }
+}
+
+struct ValueDataGenerator<'a> {
+    out_file: BufWriter<File>,
+    game: GameReader<'a>,
+    rows_written: usize,
+    rng: XorShiftRng,
+    freq: Vec<u64>,
+}
+
+impl<'a> ValueDataGenerator<'a> {
+    fn label(&mut self, state: &State, game_result: GameResult, eval: Option<Eval>) -> Vec<f32> {
+        let config = self.game.config;
+        let white_to_move = state.board().side_to_move() == chess::Color::White;
+        let crnt_result = if white_to_move {
+            game_result
+        } else {
+            game_result.flip()
+        };
+        let mut result = crnt_result;
+        let mut from_tablebase = false;
+        let mut extra = None;
+        if let Some(mode) = config.tb_labels {
+            let tb_result = tablebase_result(state);
+            if let Some(tb_result) = tb_result {
+                self.game.stats.tb_labels += 1;
+                if tb_result != crnt_result {
+                    self.game.stats.tb_disagreements += 1;
                 }
-                f.write_frequency(&mut self.freq);
             }
-            state.make_move(&m);
+            match (mode, tb_result) {
+                (TbLabels::Replace, Some(x)) => {
+                    result = x;
+                    from_tablebase = true;
+                }
+                (TbLabels::Replace, None) => {}
+                (TbLabels::Extra, x) => extra = Some(x.map_or(-1., |x| x as usize as f32)),
+            }
         }
+        let mut label = if config.eval_weight > 0. {
+            let mut probs = [0.; NUM_OUTCOMES];
+            probs[result as usize] = 1.;
+            // the tablebase result is exact, so an eval cannot improve on it
+            if let Some(eval) = eval.filter(|_| !from_tablebase) {
+                self.game.stats.eval_labels += 1;
+                let w = config.eval_weight;
+                for (x, y) in probs.iter_mut().zip(eval_probabilities(eval, white_to_move).iter()) {
+                    *x = (1. - w) * *x + w * y;
+                }
+            }
+            probs.to_vec()
+        } else {
+            vec![result as usize as f32]
+        };
+        label.extend(extra);
+        label
+    }
+}
+
+impl<'a> Visitor for ValueDataGenerator<'a> {
+    type Result = ();
+
+    fn begin_game(&mut self) {
+        self.game.begin_game();
+    }
+
+    fn san(&mut self, san: SanPlus) {
+        self.game.san(san);
+    }
+
+    fn comment(&mut self, comment: RawComment) {
+        self.game.comment(comment);
+    }
+
+    fn end_headers(&mut self) -> Skip {
+        Skip(self.game.end_headers() || self.rows_written == NUM_ROWS)
+    }
+
+    fn header(&mut self, key: &[u8], value: RawHeader) {
+        self.game.header(key, value);
+    }
+
+    fn outcome(&mut self, outcome: Option<Outcome>) {
+        self.game.outcome(outcome);
     }
 
     fn begin_variation(&mut self) -> Skip {
         Skip(true) // stay in the mainline
     }
 
-    fn end_game(&mut self) -> Self::Result {}
+    fn end_game(&mut self) -> Self::Result {
+        let game_result = match self.game.end_game() {
+            Some(x) if self.rows_written < NUM_ROWS => x,
+            _ => return,
+        };
+        let (mut state, moves) = self.game.state.extract();
+        let config = self.game.config;
+        let freq = config.samples as f64 / moves.len() as f64;
+        for (i, m) in moves.into_iter().enumerate() {
+            if i >= config.skip_plies && self.rng.gen_range(0., 1.) < freq {
+                if config.quiet_only {
+                    if let Some(noise) = noise(&state) {
+                        self.game.stats.noisy[noise as usize] += 1;
+                        state.make_move(&m);
+                        continue;
+                    }
+                }
+                let mut f = featurize(&state);
+                self.rows_written += 1;
+                let eval = if i > 0 { self.game.evals[i - 1] } else { None };
+                let label = self.label(&state, game_result, eval);
+                f.write_row(&mut self.out_file, DataFormat::Binary, &label, |_| true);
+                f.write_frequency(&mut self.freq);
+            }
+            state.make_move(&m);
+        }
+    }
 }
 
 fn write_feature_names() {
@@ -115,38 +529,124 @@ fn write_policy_feature_names() {
     }
 }
 
+/// Splits a PGN into pieces of about `chunk_bytes` that each start at a game.
+fn split_games(pgn: &[u8], chunk_bytes: usize) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < pgn.len() {
+        let end = min(start + chunk_bytes, pgn.len());
+        let end = pgn[end..]
+            .windows(8)
+            .position(|x| x == b"\n[Event ")
+            .map_or(pgn.len(), |i| end + i + 1);
+        chunks.push(&pgn[start..end]);
+        start = end;
+    }
+    chunks
+}
+
+/// Runs `f` on every chunk across `threads` workers and returns the results in
+/// chunk order.
+fn map_chunks<'a, T, F>(chunks: &[&'a [u8]], threads: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize, &'a [u8]) -> T + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<T>>> = chunks.iter().map(|_| Mutex::new(None)).collect();
+    crossbeam::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= chunks.len() {
+                    break;
+                }
+                let result = f(i, chunks[i]);
+                *results[i].lock().unwrap() = Some(result);
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|x| x.into_inner().unwrap().unwrap())
+        .collect()
+}
+
+fn part_path(out_path: &str, chunk: usize) -> String {
+    format!("{}.part{}", out_path, chunk)
+}
+
+/// Concatenates the binary part files in order, keeping the features that pass
+/// `whitelist`, and deletes them.
+fn merge_parts<F: Fn(usize) -> bool>(
+    out_path: &str,
+    num_parts: usize,
+    format: DataFormat,
+    kind: DataKind,
+    whitelist: F,
+) {
+    let mut out_file = train_data::create(out_path, format, kind).expect("create");
+    for chunk in 0..num_parts {
+        let path = part_path(out_path, chunk);
+        for row in DataReader::open(&path, kind).expect("fopen") {
+            let row = row.expect("read");
+            let entries: Vec<_> = row
+                .features
+                .into_iter()
+                .filter(|&(i, _)| whitelist(i))
+                .collect();
+            train_data::write_row(&mut out_file, format, &row.label, &entries).expect("write");
+        }
+        fs::remove_file(&path).expect("remove");
+    }
+    out_file.flush().expect("write");
+}
+
 fn run_value_gen(
-    in_path: &str,
-    out_file: Option<BufWriter<File>>,
-    whitelist: [bool; NUM_FEATURES],
-) -> ValueDataGenerator {
+    pgn: &[u8],
+    chunk: usize,
+    out_path: &str,
+    config: &TrainingConfig,
+) -> (Vec<u64>, SkipStats) {
+    let path = part_path(out_path, chunk);
     let mut generator = ValueDataGenerator {
-        freq: [0; NUM_FEATURES],
-        whitelist,
-        out_file,
-        state: StateBuilder::default(),
-        skip: true,
+        freq: vec![0; NUM_FEATURES],
+        out_file: train_data::create(&path, DataFormat::Binary, DataKind::Value).expect("create"),
+        game: GameReader::new(config, config.min_elo),
         rows_written: 0,
-        rng: SeedableRng::from_seed([1, 2, 3, 4]),
+        rng: SeedableRng::from_seed([config.seed, 2, 3, 4 + chunk as u32]),
     };
 
-    let file = File::open(in_path).expect("fopen");
-    let pgn = unsafe { Mmap::map(&file).expect("mmap") };
-    BufferedReader::new(&pgn[..])
-        .read_all(&mut generator)
-        .unwrap();
+    BufferedReader::new(pgn).read_all(&mut generator).unwrap();
+    generator.out_file.flush().expect("write");
 
-    generator
+    (generator.freq, generator.game.stats)
 }
 
-pub fn train_value(in_path: &str, out_path: &str) {
-    let freq = run_value_gen(in_path, None, [true; NUM_FEATURES]).freq;
-    let out_file = BufWriter::new(File::create(out_path).expect("create"));
+pub fn train_value(in_path: &str, out_path: &str, config: &TrainingConfig) {
+    let file = File::open(in_path).expect("fopen");
+    let pgn = unsafe { Mmap::map(&file).expect("mmap") };
+    let chunks = split_games(&pgn[..], CHUNK_BYTES);
+    let mut freq = [0u64; NUM_FEATURES];
+    let mut stats = SkipStats::default();
+    for (chunk_freq, chunk_stats) in
+        map_chunks(&chunks, config.threads, |i, x| {
+            run_value_gen(x, i, out_path, config)
+        })
+    {
+        for (x, y) in freq.iter_mut().zip(chunk_freq) {
+            *x += y;
+        }
+        stats.add(&chunk_stats);
+    }
+    stats.print();
     let mut whitelist = [false; NUM_FEATURES];
     for i in 0..NUM_FEATURES {
-        whitelist[i] = freq[i] >= 500;
+        whitelist[i] = freq[i] >= config.min_frequency;
     }
-    run_value_gen(in_path, Some(out_file), whitelist);
+    merge_parts(out_path, chunks.len(), config.format, DataKind::Value, |x| {
+        whitelist[x]
+    });
     let mut freq_file = File::create("frequencies.debug.txt").expect("create");
     let mut indices = (0..NUM_FEATURES).map(|x| (freq[x], x)).collect::<Vec<_>>();
     indices.sort_unstable();
@@ -159,66 +659,81 @@ pub fn train_value(in_path: &str, out_path: &str) {
     }
 }
 
-pub fn train(in_path: &str, out_path: &str, policy: bool) {
+/// Featurizes a PGN using `config.threads` workers; the output does not depend
+/// on the number of threads.
+pub fn train(in_path: &str, out_path: &str, policy: bool, config: &TrainingConfig) {
     write_feature_names();
     write_policy_feature_names();
     if policy {
-        train_policy(in_path, out_path);
+        train_policy(in_path, out_path, config);
     } else {
-        train_value(in_path, out_path);
+        train_value(in_path, out_path, config);
     }
 }
 
-pub fn train_policy(in_path: &str, out_path: &str) {
-    let out_path = format!("policy_{}", out_path);
-
-    let out_file = BufWriter::new(File::create(out_path).expect("create"));
-    let key_file = BufWriter::new(File::create("policy_key.txt").expect("create"));
+fn run_policy_gen(
+    pgn: &[u8],
+    chunk: usize,
+    out_path: &str,
+    config: &TrainingConfig,
+) -> (Vec<u8>, SkipStats) {
+    let path = part_path(out_path, chunk);
     let mut generator = PolicyDataGenerator {
-        out_file,
-        key_file,
-        state: StateBuilder::default(),
-        skip: true,
+        out_file: train_data::create(&path, DataFormat::Binary, DataKind::Policy).expect("create"),
+        key: Vec::new(),
+        game: GameReader::new(config, config.policy_min_elo),
     };
+    BufferedReader::new(pgn).read_all(&mut generator).unwrap();
+    generator.out_file.flush().expect("write");
+    (generator.key, generator.game.stats)
+}
+
+pub fn train_policy(in_path: &str, out_path: &str, config: &TrainingConfig) {
+    let out_path = format!("policy_{}", out_path);
+
     let file = File::open(in_path).expect("fopen");
     let pgn = unsafe { Mmap::map(&file).expect("mmap") };
-    BufferedReader::new(&pgn[..])
-        .read_all(&mut generator)
-        .unwrap();
+    let chunks = split_games(&pgn[..], CHUNK_BYTES);
+    let keys = map_chunks(&chunks, config.threads, |i, x| {
+        run_policy_gen(x, i, &out_path, config)
+    });
+    let mut key_file = BufWriter::new(File::create("policy_key.txt").expect("create"));
+    let mut stats = SkipStats::default();
+    for (key, chunk_stats) in keys {
+        key_file.write_all(&key).expect("write");
+        stats.add(&chunk_stats);
+    }
+    stats.print();
+    merge_parts(&out_path, chunks.len(), config.format, DataKind::Policy, |_| true);
 }
 
-struct PolicyDataGenerator {
+struct PolicyDataGenerator<'a> {
     out_file: BufWriter<File>,
-    key_file: BufWriter<File>,
-    state: StateBuilder,
-    skip: bool,
+    key: Vec<u8>,
+    game: GameReader<'a>,
 }
 
-impl Visitor for PolicyDataGenerator {
+impl<'a> Visitor for PolicyDataGenerator<'a> {
     type Result = ();
 
     fn begin_game(&mut self) {
-        self.state = StateBuilder::default();
-        self.skip = false;
+        self.game.begin_game();
     }
 
     fn san(&mut self, san: SanPlus) {
-        if let Ok(m) = san.san.to_move(self.state.chess()) {
-            self.state.make_move(m);
-        }
+        self.game.san(san);
     }
 
     fn end_headers(&mut self) -> Skip {
-        Skip(self.skip)
+        Skip(self.game.end_headers())
     }
 
     fn header(&mut self, key: &[u8], value: RawHeader) {
-        if key == b"WhiteElo" || key == b"BlackElo" {
-            let elo: i32 = value.decode_utf8().unwrap().parse().unwrap();
-            if elo < MIN_ELO_POLICY {
-                self.skip = true;
-            }
-        }
+        self.game.header(key, value);
+    }
+
+    fn outcome(&mut self, outcome: Option<Outcome>) {
+        self.game.outcome(outcome);
     }
 
     fn begin_variation(&mut self) -> Skip {
@@ -226,17 +741,143 @@ impl Visitor for PolicyDataGenerator {
     }
 
     fn end_game(&mut self) -> Self::Result {
-        let (mut state, moves) = self.state.extract();
+        if self.game.end_game().is_none() {
+            return;
+        }
+        let (mut state, moves) = self.game.state.extract();
         for m in moves {
             let legals = state.available_moves();
             let legals = legals.as_slice();
-            let index = legals.iter().position(|x| m == *x).unwrap();
-            writeln!(self.key_file, "{} {}", legals.len(), index).unwrap();
+            // games with an illegal move were skipped, so every move is found
+            let index = legals.iter().position(|x| m == *x).expect("legal move");
+            writeln!(self.key, "{} {}", legals.len(), index).unwrap();
             for opt in legals {
-                policy_features::featurize(&state, opt)
-                    .write_libsvm(&mut self.out_file, 0, |_| true);
+                policy_features::featurize(&state, opt).write_row(
+                    &mut self.out_file,
+                    DataFormat::Binary,
+                    &[0.],
+                    |_| true,
+                );
             }
             state.make_move(&m);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_at_games() {
+        let pgn = b"[Event \"a\"]\n\n1. e4 1-0\n\n[Event \"b\"]\n\n1. d4 0-1\n\n[Event \"c\"]\n";
+        let chunks = split_games(pgn, 4);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|x| x.starts_with(b"[Event ")));
+        assert_eq!(chunks.concat(), &pgn[..]);
+        assert_eq!(split_games(pgn, 1000), vec![&pgn[..]]);
+    }
+
+    struct Counter<'a>(GameReader<'a>);
+
+    impl<'a> Visitor for Counter<'a> {
+        type Result = ();
+        fn begin_game(&mut self) {
+            self.0.begin_game();
+        }
+        fn header(&mut self, key: &[u8], value: RawHeader) {
+            self.0.header(key, value);
+        }
+        fn end_headers(&mut self) -> Skip {
+            Skip(self.0.end_headers())
+        }
+        fn san(&mut self, san: SanPlus) {
+            self.0.san(san);
+        }
+        fn outcome(&mut self, outcome: Option<Outcome>) {
+            self.0.outcome(outcome);
+        }
+        fn end_game(&mut self) {
+            self.0.end_game();
+        }
+    }
+
+    #[test]
+    fn skip_stats() {
+        let pgn = b"[WhiteElo \"2000\"]\n\n1. e4 e5 1-0\n\n\
+            [WhiteElo \"?\"]\n\n1. e4 1-0\n\n\
+            [WhiteElo \"1000\"]\n\n1. e4 1-0\n\n\
+            [Variant \"Chess960\"]\n\n1. e4 1-0\n\n\
+            [Variant \"Standard\"]\n\n1. e4 Ke7 0-1\n\n\
+            [WhiteElo \"\"]\n\n1. e4 *\n\n\
+            [Event \"x\"]\n\n1. e4 e5\n\n";
+        let config = TrainingConfig::default();
+        let mut counter = Counter(GameReader::new(&config, 1700));
+        BufferedReader::new(&pgn[..]).read_all(&mut counter).unwrap();
+        let stats = counter.0.stats;
+        assert_eq!(stats.games, 7);
+        assert_eq!(stats.skipped[SkipReason::LowElo as usize], 1);
+        assert_eq!(stats.skipped[SkipReason::BadElo as usize], 2);
+        assert_eq!(stats.skipped[SkipReason::Variant as usize], 1);
+        assert_eq!(stats.skipped[SkipReason::IllegalSan as usize], 1);
+        assert_eq!(stats.skipped[SkipReason::MissingResult as usize], 1);
+    }
+
+    #[test]
+    fn quiet_positions() {
+        let noise_of = |fen: &str| noise(&State::from_fen(fen).unwrap());
+        assert_eq!(noise_of("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), None);
+        assert_eq!(noise_of("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1"), Some(Noise::InCheck));
+        assert_eq!(
+            noise_of("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1"),
+            Some(Noise::WinningCapture)
+        );
+        assert_eq!(noise_of("4k3/8/2p5/3p4/8/8/8/3RK3 w - - 0 1"), None);
+
+        let mut state = State::from_fen("4k3/8/8/3p4/4P3/8/8/3QK3 b - - 0 1").unwrap();
+        let mov = chess::ChessMove::from_san(state.board(), "dxe4").unwrap();
+        state.make_move(&mov);
+        assert_eq!(noise(&state), None);
+        let mut state = State::from_fen("4k3/8/8/3p4/4P3/8/8/4K2Q b - - 0 1").unwrap();
+        state.make_move(&mov);
+        assert_eq!(noise(&state), Some(Noise::PendingRecapture));
+    }
+
+    #[test]
+    fn evals() {
+        assert_eq!(
+            parse_eval(b" [%eval 0.35] [%clk 0:03:00] "),
+            Some(Eval::Centipawns(35.))
+        );
+        assert_eq!(parse_eval(b"[%eval #-3]"), Some(Eval::Mate(-3)));
+        assert_eq!(parse_eval(b"[%clk 0:03:00]"), None);
+
+        assert_eq!(eval_probabilities(Eval::Mate(-3), true), [0., 1., 0.]);
+        assert_eq!(eval_probabilities(Eval::Mate(-3), false), [1., 0., 0.]);
+        let even = eval_probabilities(Eval::Centipawns(0.), true);
+        assert_eq!(even[0], even[1]);
+        let ahead = eval_probabilities(Eval::Centipawns(300.), false);
+        assert!(ahead[1] > 0.8 && ahead[0] < 0.05);
+        assert!((ahead.iter().sum::<f32>() - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn header_filters() {
+        let pgn = b"[TimeControl \"180+2\"]\n[Termination \"Normal\"]\n\n1. e4 1-0\n\n\
+            [TimeControl \"60+0\"]\n[Termination \"Normal\"]\n\n1. e4 1-0\n\n\
+            [TimeControl \"180+2\"]\n[Termination \"Time forfeit\"]\n\n1. e4 1-0\n\n\
+            [Termination \"Normal\"]\n[Variant \"Crazyhouse\"]\n\n1. e4 1-0\n\n";
+        let config = TrainingConfig {
+            time_controls: vec!["180+2".into(), "300+0".into()],
+            terminations: vec!["normal".into()],
+            ..TrainingConfig::default()
+        };
+        let mut counter = Counter(GameReader::new(&config, 0));
+        BufferedReader::new(&pgn[..]).read_all(&mut counter).unwrap();
+        let stats = counter.0.stats;
+        assert_eq!(stats.games, 4);
+        assert_eq!(stats.skipped[SkipReason::TimeControl as usize], 2);
+        assert_eq!(stats.skipped[SkipReason::Termination as usize], 1);
+        assert_eq!(stats.skipped.iter().sum::<usize>(), 3);
+    }
+}
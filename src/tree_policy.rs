@@ -1,4 +1,5 @@
 extern crate rand;
+use self::rand::distributions::{Gamma, IndependentSample};
 use self::rand::{Rng, SeedableRng, XorShiftRng};
 
 use mcts::MCTS;
@@ -25,6 +26,8 @@ pub trait TreePolicy<Spec: MCTS<TreePolicy = Self>>: Sync + Sized {
         handle: SearchHandle<Spec>,
     ) -> MoveInfoHandle<'a, Spec>;
     fn validate_evaluations(&self, _evalns: &[Self::MoveEvaluation]) {}
+    /// Called on the move evaluations of each new node before they are stored.
+    fn adjust_evaluations(&self, _evalns: &mut [Self::MoveEvaluation], _at_root: bool) {}
 }
 
 #[derive(Clone, Debug)]
@@ -49,17 +52,106 @@ impl UCTPolicy {
     }
 }
 
+/// The reward `AlphaGoPolicy` assumes for children that have not been visited yet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FirstPlayUrgency {
+    /// The parent's average reward minus this amount.
+    Reduction(f32),
+    /// This fixed reward.
+    Absolute(f32),
+    /// The reward of a loss (the virtual loss).
+    Loss,
+}
+
+impl FirstPlayUrgency {
+    pub fn scaled(self, scale: f32) -> Self {
+        match self {
+            FirstPlayUrgency::Reduction(x) => FirstPlayUrgency::Reduction(x * scale),
+            FirstPlayUrgency::Absolute(x) => FirstPlayUrgency::Absolute(x * scale),
+            FirstPlayUrgency::Loss => FirstPlayUrgency::Loss,
+        }
+    }
+}
+
+/// Dirichlet noise mixed into the priors of the root.
+#[derive(Clone, Copy, Debug)]
+pub struct DirichletNoise {
+    alpha: f32,
+    epsilon: f32,
+    seed: u32,
+}
+
+impl DirichletNoise {
+    /// Replaces a fraction `epsilon` of each prior by a sample of `Dir(alpha)`.
+    pub fn new(alpha: f32, epsilon: f32, seed: u32) -> Self {
+        assert!(
+            alpha > 0.0,
+            "Dirichlet alpha is {} (must be positive)",
+            alpha
+        );
+        Self {
+            alpha,
+            epsilon,
+            seed,
+        }
+    }
+
+    fn apply(&self, priors: &mut [f32]) {
+        if priors.len() < 2 {
+            return;
+        }
+        let mut rng = PolicyRng::with_seed(self.seed).rng;
+        let gamma = Gamma::new(f64::from(self.alpha), 1.);
+        let noise: Vec<f64> = priors.iter().map(|_| gamma.ind_sample(&mut rng)).collect();
+        let total: f64 = noise.iter().sum();
+        if total <= 0. {
+            return;
+        }
+        for (x, n) in priors.iter_mut().zip(noise) {
+            *x = (1. - self.epsilon) * *x + self.epsilon * (n / total) as f32;
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct AlphaGoPolicy {
     exploration_constant: f32,
+    root_fpu: FirstPlayUrgency,
+    interior_fpu: FirstPlayUrgency,
+    root_temperature: f32,
+    interior_temperature: f32,
+    root_noise: Option<DirichletNoise>,
 }
 
 impl AlphaGoPolicy {
     pub fn new(exploration_constant: f32) -> Self {
         Self {
             exploration_constant,
+            root_fpu: FirstPlayUrgency::Absolute(0.),
+            interior_fpu: FirstPlayUrgency::Absolute(0.),
+            root_temperature: 1.,
+            interior_temperature: 1.,
+            root_noise: None,
+        }
+    }
+    pub fn with_fpu(self, root_fpu: FirstPlayUrgency, interior_fpu: FirstPlayUrgency) -> Self {
+        Self {
+            root_fpu,
+            interior_fpu,
+            ..self
+        }
+    }
+    /// Softmax temperatures for the priors of the root and of the other nodes.
+    pub fn with_temperature(self, root_temperature: f32, interior_temperature: f32) -> Self {
+        Self {
+            root_temperature,
+            interior_temperature,
+            ..self
         }
     }
+    pub fn with_root_noise(self, root_noise: Option<DirichletNoise>) -> Self {
+        Self { root_noise, ..self }
+    }
     pub fn exploration_constant(&self) -> f32 {
         self.exploration_constant
     }
@@ -97,19 +189,80 @@ impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for UCTPolicy {
     }
 }
 
-impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for AlphaGoPolicy {
-    type ThreadLocalData = PolicyRng;
-    type MoveEvaluation = f32;
+/// The reward assumed for the unvisited children of the node being searched.
+fn unvisited_reward<Spec: MCTS>(
+    root_fpu: FirstPlayUrgency,
+    interior_fpu: FirstPlayUrgency,
+    moves: Moves<Spec>,
+    handle: &SearchHandle<Spec>,
+) -> f32 {
+    let fpu = if handle.depth() == 0 {
+        root_fpu
+    } else {
+        interior_fpu
+    };
+    match fpu {
+        FirstPlayUrgency::Absolute(x) => x,
+        FirstPlayUrgency::Loss => -(handle.mcts().virtual_loss() as f32),
+        FirstPlayUrgency::Reduction(x) => {
+            let visits = moves.map(|x| x.visits()).sum::<u64>();
+            let sum_rewards = moves.map(|x| x.sum_rewards() as f32).sum::<f32>();
+            let parent_q = match visits {
+                0 => 0.,
+                visits => sum_rewards / visits as f32,
+            };
+            parent_q - x
+        }
+    }
+}
 
-    fn choose_child<'a>(
+fn validate_priors(evalns: &[f32]) {
+    for &x in evalns {
+        assert!(
+            x >= -1e-6,
+            "Move evaluation is {} (must be non-negative)",
+            x
+        );
+    }
+    if !evalns.is_empty() {
+        let evaln_sum: f32 = evalns.iter().sum();
+        assert!(
+            (evaln_sum - 1.0).abs() < 0.1,
+            "Sum of evaluations is {} (should sum to 1)",
+            evaln_sum
+        );
+    }
+}
+
+/// Raises `priors` to the power `1 / temperature` and renormalizes them, which is
+/// the same as dividing the logits of a softmax by `temperature`.
+fn apply_temperature(priors: &mut [f32], temperature: f32) {
+    if (temperature - 1.).abs() < f32::EPSILON || priors.is_empty() {
+        return;
+    }
+    let exponent = 1. / temperature;
+    for x in priors.iter_mut() {
+        *x = x.powf(exponent);
+    }
+    let s = 1. / priors.iter().sum::<f32>();
+    for x in priors.iter_mut() {
+        *x *= s;
+    }
+}
+
+impl AlphaGoPolicy {
+    fn select<'a, Spec: MCTS>(
         &self,
-        _: &Spec::State,
         moves: Moves<'a, Spec>,
         mut handle: SearchHandle<Spec>,
-    ) -> MoveInfoHandle<'a, Spec> {
+    ) -> MoveInfoHandle<'a, Spec>
+    where
+        Spec::TreePolicy: TreePolicy<Spec, MoveEvaluation = f32, ThreadLocalData = PolicyRng>,
+    {
         let total_visits = moves.map(|x| x.visits()).sum::<u64>() + 1;
         let sqrt_total_visits = (total_visits as f32).sqrt();
         let explore_coef = self.exploration_constant * sqrt_total_visits;
+        let unvisited_reward = unvisited_reward(self.root_fpu, self.interior_fpu, moves, &handle);
         handle
             .thread_data()
             .policy_data
@@ -117,6 +270,9 @@ impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for AlphaGoPolicy {
                 let sum_rewards = mov.sum_rewards() as f32;
                 let child_visits = mov.visits();
                 let policy_evaln = *mov.move_evaluation() as f32;
+                if child_visits == 0 {
+                    return (unvisited_reward + explore_coef * policy_evaln).into();
+                }
                 Fraction(
                     sum_rewards + explore_coef * policy_evaln,
                     (child_visits + 1) as f32,
@@ -125,21 +281,187 @@ impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for AlphaGoPolicy {
             .unwrap()
     }
 
+    fn adjust(&self, evalns: &mut [f32], at_root: bool) {
+        if at_root {
+            apply_temperature(evalns, self.root_temperature);
+            if let Some(noise) = self.root_noise {
+                noise.apply(evalns);
+            }
+        } else {
+            apply_temperature(evalns, self.interior_temperature);
+        }
+    }
+}
+
+impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for AlphaGoPolicy {
+    type ThreadLocalData = PolicyRng;
+    type MoveEvaluation = f32;
+
+    fn choose_child<'a>(
+        &self,
+        _: &Spec::State,
+        moves: Moves<'a, Spec>,
+        handle: SearchHandle<Spec>,
+    ) -> MoveInfoHandle<'a, Spec> {
+        self.select(moves, handle)
+    }
+
     fn validate_evaluations(&self, evalns: &[f32]) {
-        for &x in evalns {
-            assert!(
-                x >= -1e-6,
-                "Move evaluation is {} (must be non-negative)",
-                x
-            );
+        validate_priors(evalns)
+    }
+
+    fn adjust_evaluations(&self, evalns: &mut [f32], at_root: bool) {
+        self.adjust(evalns, at_root)
+    }
+}
+
+/// AlphaZero's PUCT: the average reward plus `cpuct * prior * sqrt(N) / (1 + n)`,
+/// where `cpuct` grows with the log of the parent's visits `N`. `N` counts as at
+/// least 1, so the first visit through a node still follows the priors.
+#[derive(Clone, Debug)]
+pub struct PuctPolicy {
+    cpuct_init: f32,
+    cpuct_base: f32,
+    cpuct_factor: f32,
+    root_fpu: FirstPlayUrgency,
+    interior_fpu: FirstPlayUrgency,
+    root_temperature: f32,
+    interior_temperature: f32,
+    root_noise: Option<DirichletNoise>,
+}
+
+impl PuctPolicy {
+    pub fn new(cpuct_init: f32, cpuct_base: f32, cpuct_factor: f32) -> Self {
+        assert!(
+            cpuct_base > 0.0,
+            "cpuct base is {} (must be positive)",
+            cpuct_base
+        );
+        Self {
+            cpuct_init,
+            cpuct_base,
+            cpuct_factor,
+            root_fpu: FirstPlayUrgency::Absolute(0.),
+            interior_fpu: FirstPlayUrgency::Absolute(0.),
+            root_temperature: 1.,
+            interior_temperature: 1.,
+            root_noise: None,
         }
-        if !evalns.is_empty() {
-            let evaln_sum: f32 = evalns.iter().sum();
-            assert!(
-                (evaln_sum - 1.0).abs() < 0.1,
-                "Sum of evaluations is {} (should sum to 1)",
-                evaln_sum
-            );
+    }
+    pub fn with_fpu(self, root_fpu: FirstPlayUrgency, interior_fpu: FirstPlayUrgency) -> Self {
+        Self {
+            root_fpu,
+            interior_fpu,
+            ..self
+        }
+    }
+    /// Softmax temperatures for the priors of the root and of the other nodes.
+    pub fn with_temperature(self, root_temperature: f32, interior_temperature: f32) -> Self {
+        Self {
+            root_temperature,
+            interior_temperature,
+            ..self
+        }
+    }
+    pub fn with_root_noise(self, root_noise: Option<DirichletNoise>) -> Self {
+        Self { root_noise, ..self }
+    }
+    pub fn cpuct(&self, parent_visits: u64) -> f32 {
+        let growth = ((parent_visits as f32 + self.cpuct_base + 1.) / self.cpuct_base).ln();
+        self.cpuct_init + self.cpuct_factor * growth
+    }
+
+    fn select<'a, Spec: MCTS>(
+        &self,
+        moves: Moves<'a, Spec>,
+        mut handle: SearchHandle<Spec>,
+    ) -> MoveInfoHandle<'a, Spec>
+    where
+        Spec::TreePolicy: TreePolicy<Spec, MoveEvaluation = f32, ThreadLocalData = PolicyRng>,
+    {
+        let total_visits = moves.map(|x| x.visits()).sum::<u64>();
+        let explore_coef = self.cpuct(total_visits) * (total_visits.max(1) as f32).sqrt();
+        let unvisited_reward = unvisited_reward(self.root_fpu, self.interior_fpu, moves, &handle);
+        handle
+            .thread_data()
+            .policy_data
+            .select_by_key(moves, |mov| {
+                let child_visits = mov.visits();
+                let explore = explore_coef * *mov.move_evaluation() / (child_visits + 1) as f32;
+                let q = match child_visits {
+                    0 => unvisited_reward,
+                    n => mov.sum_rewards() as f32 / n as f32,
+                };
+                (q + explore).into()
+            })
+            .unwrap()
+    }
+
+    fn adjust(&self, evalns: &mut [f32], at_root: bool) {
+        if at_root {
+            apply_temperature(evalns, self.root_temperature);
+            if let Some(noise) = self.root_noise {
+                noise.apply(evalns);
+            }
+        } else {
+            apply_temperature(evalns, self.interior_temperature);
+        }
+    }
+}
+
+impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for PuctPolicy {
+    type ThreadLocalData = PolicyRng;
+    type MoveEvaluation = f32;
+
+    fn choose_child<'a>(
+        &self,
+        _: &Spec::State,
+        moves: Moves<'a, Spec>,
+        handle: SearchHandle<Spec>,
+    ) -> MoveInfoHandle<'a, Spec> {
+        self.select(moves, handle)
+    }
+
+    fn validate_evaluations(&self, evalns: &[f32]) {
+        validate_priors(evalns)
+    }
+
+    fn adjust_evaluations(&self, evalns: &mut [f32], at_root: bool) {
+        self.adjust(evalns, at_root)
+    }
+}
+
+/// A tree policy chosen at runtime, so that policies can be compared in matches.
+#[derive(Clone, Debug)]
+pub enum SearchPolicy {
+    AlphaGo(AlphaGoPolicy),
+    Puct(PuctPolicy),
+}
+
+impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for SearchPolicy {
+    type ThreadLocalData = PolicyRng;
+    type MoveEvaluation = f32;
+
+    fn choose_child<'a>(
+        &self,
+        _: &Spec::State,
+        moves: Moves<'a, Spec>,
+        handle: SearchHandle<Spec>,
+    ) -> MoveInfoHandle<'a, Spec> {
+        match *self {
+            SearchPolicy::AlphaGo(ref p) => p.select(moves, handle),
+            SearchPolicy::Puct(ref p) => p.select(moves, handle),
+        }
+    }
+
+    fn validate_evaluations(&self, evalns: &[f32]) {
+        validate_priors(evalns)
+    }
+
+    fn adjust_evaluations(&self, evalns: &mut [f32], at_root: bool) {
+        match *self {
+            SearchPolicy::AlphaGo(ref p) => p.adjust(evalns, at_root),
+            SearchPolicy::Puct(ref p) => p.adjust(evalns, at_root),
         }
     }
 }
@@ -151,7 +473,11 @@ pub struct PolicyRng {
 
 impl PolicyRng {
     pub fn new() -> Self {
-        let rng = SeedableRng::from_seed([1, 2, 3, 4]);
+        Self::with_seed(1)
+    }
+
+    pub fn with_seed(seed: u32) -> Self {
+        let rng = SeedableRng::from_seed([seed, 2, 3, 4]);
         Self { rng }
     }
 
@@ -187,3 +513,90 @@ impl Default for PolicyRng {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evaluation::SearchEval;
+    use mcts::MCTSManager;
+    use search::{GooseMCTS, SCALE};
+    use search_tree::empty_previous_table;
+    use state::State;
+    use transposition_table::ApproxTable;
+
+    #[test]
+    fn temperature() {
+        let mut priors = vec![0.5, 0.3, 0.2];
+        apply_temperature(&mut priors, 1.);
+        assert_eq!(priors, vec![0.5, 0.3, 0.2]);
+
+        apply_temperature(&mut priors, 2.);
+        assert!((priors.iter().sum::<f32>() - 1.).abs() < 1e-6);
+        assert!(priors[0] < 0.5 && priors[2] > 0.2);
+        assert!(priors[0] > priors[1] && priors[1] > priors[2]);
+
+        let policy = AlphaGoPolicy::new(1.).with_temperature(2., 1.);
+        let mut root = vec![0.5, 0.3, 0.2];
+        policy.adjust(&mut root, true);
+        assert!(root[0] < 0.5 && root[2] > 0.2);
+        let mut interior = vec![0.5, 0.3, 0.2];
+        policy.adjust(&mut interior, false);
+        assert_eq!(interior, vec![0.5, 0.3, 0.2]);
+    }
+
+    #[test]
+    fn dirichlet_noise() {
+        let priors = vec![0.7, 0.2, 0.1];
+        let mut a = priors.clone();
+        DirichletNoise::new(0.3, 0.25, 7).apply(&mut a);
+        assert!((a.iter().sum::<f32>() - 1.).abs() < 1e-5);
+        assert!(a.iter().zip(&priors).all(|(x, p)| *x >= 0.75 * p - 1e-6));
+        assert!(a != priors);
+
+        let mut b = priors.clone();
+        DirichletNoise::new(0.3, 0.25, 7).apply(&mut b);
+        assert_eq!(a, b);
+        let mut c = priors.clone();
+        DirichletNoise::new(0.3, 0.25, 8).apply(&mut c);
+        assert!(a != c);
+    }
+
+    #[test]
+    fn cpuct_grows_with_visits() {
+        let policy = PuctPolicy::new(2., 19652., 2.);
+        assert!((policy.cpuct(0) - 2.).abs() < 1e-3);
+        assert!(policy.cpuct(100_000) > policy.cpuct(1_000));
+        assert!((policy.cpuct(19652) - (2. + 2. * 2f32.ln())).abs() < 1e-3);
+    }
+
+    #[test]
+    fn puct_first_visit_follows_prior() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "4k3/8/8/3q4/8/8/7P/3QK3 w - - 0 1",
+        ];
+        for fen in &fens {
+            let policy = PuctPolicy::new(2. * SCALE, 19652., 2. * SCALE);
+            let mut manager = MCTSManager::new(
+                State::from_fen(fen).unwrap().freeze(),
+                GooseMCTS,
+                SearchEval::new(),
+                SearchPolicy::Puct(policy),
+                ApproxTable::enough_to_hold(1000),
+                empty_previous_table(),
+            );
+            manager.playout_n(1);
+            let root = manager.tree().root_node();
+            let best_prior = root
+                .moves()
+                .max_by(|a, b| {
+                    a.move_evaluation()
+                        .partial_cmp(b.move_evaluation())
+                        .unwrap()
+                })
+                .unwrap();
+            assert_eq!(best_prior.visits(), 1, "{}", fen);
+        }
+    }
+}
@@ -1,4 +1,10 @@
-use options::{set_hash_size_mb, set_num_threads};
+use eval_cache::clear_eval_cache;
+use network::set_network_file;
+use options::{
+    set_cpuct, set_dirichlet_noise, set_eval_cache_percent, set_fpu_strategy, set_fpu_value,
+    set_gumbel_moves, set_hash_size_mb, set_mate_search_plies, set_num_threads,
+    set_policy_temperature, set_quiescence_nodes, set_use_network_eval, set_use_puct,
+};
 use search::Search;
 use search_tree::empty_previous_table;
 use state::State;
@@ -63,11 +69,103 @@ pub fn main(commands: Vec<String>) {
                                 }
                             }
                         }
+                        Some(opt) if opt.name() == "evalcacheshare" => {
+                            if let Some(v) = opt.value() {
+                                if let Ok(t) = v.parse() {
+                                    set_eval_cache_percent(t)
+                                }
+                            }
+                        }
+                        Some(opt) if opt.name() == "quiescencenodes" => {
+                            if let Some(v) = opt.value() {
+                                if let Ok(t) = v.parse() {
+                                    set_quiescence_nodes(t);
+                                    clear_eval_cache();
+                                }
+                            }
+                        }
+                        Some(opt) if opt.name() == "matesearchplies" => {
+                            if let Some(v) = opt.value() {
+                                if let Ok(t) = v.parse() {
+                                    set_mate_search_plies(t);
+                                    clear_eval_cache();
+                                }
+                            }
+                        }
+                        Some(opt) if opt.name().starts_with("fpustrategy") => {
+                            let at_root = opt.name().ends_with("atroot");
+                            match opt.value() {
+                                Some(v) if set_fpu_strategy(v, at_root) => (),
+                                _ => warn!("Unknown FPU strategy"),
+                            }
+                        }
+                        Some(opt) if opt.name().starts_with("fpuvalue") => {
+                            let at_root = opt.name().ends_with("atroot");
+                            if let Some(v) = opt.value() {
+                                if let Ok(t) = v.parse() {
+                                    set_fpu_value(t, at_root)
+                                }
+                            }
+                        }
+                        Some(opt) if opt.name() == "treepolicy" => match opt.value() {
+                            Some(v) if v == "alphago" => set_use_puct(false),
+                            Some(v) if v == "puct" => set_use_puct(true),
+                            _ => warn!("Unknown tree policy"),
+                        },
+                        Some(opt) if opt.name().starts_with("cpuct") => {
+                            let v = opt.value().as_ref().and_then(|v| v.parse().ok());
+                            match opt.name().as_str() {
+                                "cpuctinit" => set_cpuct(v, None, None),
+                                "cpuctbase" => set_cpuct(None, v, None),
+                                "cpuctfactor" => set_cpuct(None, None, v),
+                                _ => warn!("Unknown cpuct option"),
+                            }
+                        }
+                        Some(opt) if opt.name().starts_with("dirichlet") => {
+                            let v = opt.value().as_ref().and_then(|v| v.parse().ok());
+                            match opt.name().as_str() {
+                                "dirichletalpha" => set_dirichlet_noise(v, None),
+                                "dirichletepsilon" => set_dirichlet_noise(None, v),
+                                _ => warn!("Unknown Dirichlet noise option"),
+                            }
+                        }
+                        Some(opt) if opt.name() == "gumbelmoves" => {
+                            if let Some(v) = opt.value() {
+                                if let Ok(t) = v.parse() {
+                                    set_gumbel_moves(t)
+                                }
+                            }
+                        }
+                        Some(opt) if opt.name().starts_with("policytemperature") => {
+                            let at_root = opt.name().ends_with("atroot");
+                            if let Some(v) = opt.value() {
+                                if let Ok(t) = v.parse() {
+                                    set_policy_temperature(t, at_root)
+                                }
+                            }
+                        }
+                        Some(opt) if opt.name() == "evalfile" => {
+                            if let Some(path) = opt.value() {
+                                if let Err(e) = set_network_file(path) {
+                                    warn!("Couldn't load network '{}': {}", path, e)
+                                }
+                                clear_eval_cache();
+                            }
+                        }
+                        Some(opt) if opt.name() == "evaluator" => {
+                            match opt.value().as_ref().map(|v| v.to_lowercase()) {
+                                Some(ref v) if v == "linear" => set_use_network_eval(false),
+                                Some(ref v) if v == "network" => set_use_network_eval(true),
+                                _ => warn!("Unknown evaluator"),
+                            }
+                            clear_eval_cache();
+                        }
                         _ => warn!("Badly formatted or unknown option"),
                        }
                 }
                 "ucinewgame" => {
                     position_num += 1;
+                    clear_eval_cache();
                     search = Search::new(State::default(), empty_previous_table());
                 }
                 "position"   => {
@@ -92,7 +190,7 @@ pub fn main(commands: Vec<String>) {
                 "go"         => {
                     search = search.go(tokens, position_num, &sender);
                 },
-                "eval"       => search = search.print_eval(),
+                "eval"       => search = search.print_eval(tokens),
                 _ => error!("Unknown command: {} (this engine uses a reduced set of commands from the UCI protocol)", first_word)
             }
         }
@@ -104,7 +202,33 @@ pub fn uci() {
     println!("id author {}", ENGINE_AUTHOR);
     println!("option name Hash type spin min 1 max 65536 default 1");
     println!("option name Threads type spin min 1 max 255 default 1");
+    println!("option name EvalCacheShare type spin min 0 max 90 default 10");
     println!("option name SyzygyPath type string");
+    println!("option name EvalFile type string");
+    println!("option name Evaluator type combo default linear var linear var network");
+    println!("option name QuiescenceNodes type spin min 0 max 1000 default 0");
+    println!("option name MateSearchPlies type spin min 0 max 4 default 0");
+    println!("option name TreePolicy type combo default alphago var alphago var puct");
+    println!("option name CpuctInit type spin min 0 max 10000 default 200");
+    println!("option name CpuctBase type spin min 1 max 1000000 default 19652");
+    println!("option name CpuctFactor type spin min 0 max 10000 default 200");
+    println!("option name DirichletAlpha type spin min 1 max 10000 default 300");
+    println!("option name DirichletEpsilon type spin min 0 max 100 default 0");
+    println!("option name GumbelMoves type spin min 0 max 64 default 0");
+    for suffix in &["", "AtRoot"] {
+        println!(
+            "option name FpuStrategy{} type combo default absolute var reduction var absolute var loss",
+            suffix
+        );
+        println!(
+            "option name FpuValue{} type spin min -100 max 100 default 0",
+            suffix
+        );
+        println!(
+            "option name PolicyTemperature{} type spin min 1 max 1000 default 100",
+            suffix
+        );
+    }
     println!("uciok");
 }
 